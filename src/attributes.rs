@@ -0,0 +1,146 @@
+//! Catalog of the attributes that the Calyx compiler understands.
+
+/// The kinds of things an `@attribute` can be attached to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttrPosition {
+    Component,
+    Port,
+    Cell,
+    Group,
+    Control,
+}
+
+#[derive(Debug)]
+pub struct Attribute {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether the attribute takes a numeric argument, like `@static(2)`
+    pub has_value: bool,
+    pub positions: &'static [AttrPosition],
+}
+
+use AttrPosition::*;
+
+pub const CATALOG: &[Attribute] = &[
+    Attribute {
+        name: "external",
+        description: "Expose the cell's state to the testbench",
+        has_value: false,
+        positions: &[Cell],
+    },
+    Attribute {
+        name: "data",
+        description: "Only carries data, does not need to be reset",
+        has_value: false,
+        positions: &[Cell, Port],
+    },
+    Attribute {
+        name: "protected",
+        description: "Never removed by optimizations",
+        has_value: false,
+        positions: &[Cell],
+    },
+    Attribute {
+        name: "generated",
+        description: "Created by the compiler",
+        has_value: false,
+        positions: &[Cell],
+    },
+    Attribute {
+        name: "go",
+        description: "Interface port that starts the component",
+        has_value: false,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "done",
+        description: "Interface port that signals the component is finished",
+        has_value: false,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "clk",
+        description: "Clock port",
+        has_value: false,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "reset",
+        description: "Reset port",
+        has_value: false,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "stable",
+        description: "Output keeps its value after the component is done",
+        has_value: false,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "write_together",
+        description: "Ports in the same set must be written together",
+        has_value: true,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "read_together",
+        description: "Output is only valid while the paired input is driven",
+        has_value: true,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "interval",
+        description: "Cycles before the component can start again",
+        has_value: true,
+        positions: &[Port],
+    },
+    Attribute {
+        name: "static",
+        description: "Latency in cycles",
+        has_value: true,
+        positions: &[Group, Control],
+    },
+    Attribute {
+        name: "promotable",
+        description: "Can be turned into a static group or statement with this latency",
+        has_value: true,
+        positions: &[Group, Control],
+    },
+    Attribute {
+        name: "bound",
+        description: "Number of times a `while` loop runs",
+        has_value: true,
+        positions: &[Control],
+    },
+    Attribute {
+        name: "new_fsm",
+        description: "Compile into a separate FSM",
+        has_value: false,
+        positions: &[Control],
+    },
+    Attribute {
+        name: "share",
+        description: "Can be shared between groups that do not run in parallel",
+        has_value: false,
+        positions: &[Component],
+    },
+    Attribute {
+        name: "state_share",
+        description: "Stateful but can be shared when its state is not live",
+        has_value: false,
+        positions: &[Component],
+    },
+    Attribute {
+        name: "toplevel",
+        description: "The entrypoint of the program",
+        has_value: false,
+        positions: &[Component],
+    },
+];
+
+/// Attributes that can be attached at `pos`
+pub fn valid_at(pos: AttrPosition) -> impl Iterator<Item = &'static Attribute> {
+    CATALOG
+        .iter()
+        .filter(move |attr| attr.positions.contains(&pos))
+}
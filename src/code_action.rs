@@ -0,0 +1,348 @@
+//! Refactorings offered through `textDocument/codeAction`.
+
+use std::collections::{HashMap, HashSet};
+
+use tower_lsp::lsp_types as lspt;
+use tree_sitter as ts;
+
+use crate::{
+    convert::{Point, Range},
+    document::{strip_ws, Assignment, Document},
+    lint::DeleteFix,
+    ts_utils::{Descendants, ParentUntil},
+};
+
+pub trait CodeActionProvider {
+    /// The actions at `point`, including quick fixes for the `diagnostics`
+    /// there that carry one
+    fn code_actions(
+        &self,
+        point: &Point,
+        diagnostics: &[lspt::Diagnostic],
+    ) -> Vec<lspt::CodeActionOrCommand>;
+}
+
+impl CodeActionProvider for Document {
+    fn code_actions(
+        &self,
+        point: &Point,
+        diagnostics: &[lspt::Diagnostic],
+    ) -> Vec<lspt::CodeActionOrCommand> {
+        self.quick_fixes(diagnostics)
+            .into_iter()
+            .chain(self.inline_group_actions(point))
+            .map(lspt::CodeActionOrCommand::CodeAction)
+            .collect()
+    }
+}
+
+/// A group definition together with the single place it is enabled from.
+struct InlineCandidate<'a> {
+    name: String,
+    def: ts::Node<'a>,
+    enable: ts::Node<'a>,
+}
+
+impl Document {
+    /// Apply the fixes stored in lint diagnostics
+    fn quick_fixes(&self, diagnostics: &[lspt::Diagnostic]) -> Vec<lspt::CodeAction> {
+        diagnostics
+            .iter()
+            .filter_map(|diag| {
+                let fix: DeleteFix = serde_json::from_value(diag.data.clone()?).ok()?;
+                Some(lspt::CodeAction {
+                    title: fix.title,
+                    kind: Some(lspt::CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diag.clone()]),
+                    edit: Some(lspt::WorkspaceEdit {
+                        changes: Some(HashMap::from([(
+                            self.url.clone(),
+                            vec![lspt::TextEdit::new(fix.range, String::new())],
+                        )])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Offer to inline the group under `point` into the groups enabled right
+    /// before and after it. Both groups must be enabled from exactly one place.
+    fn inline_group_actions(&self, point: &Point) -> Vec<lspt::CodeAction> {
+        let Some(group) = self
+            .node_at_point(point)
+            .filter(|n| n.kind() == "ident")
+            .filter(|n| {
+                n.parent()
+                    .is_some_and(|p| p.kind() == "enable" || p.kind() == "group")
+            })
+            .and_then(|n| self.inline_candidate(n, self.node_text(&n)))
+        else {
+            return vec![];
+        };
+
+        let stmt = statement_of(group.enable);
+        [stmt.prev_named_sibling(), stmt.next_named_sibling()]
+            .into_iter()
+            .flatten()
+            .filter_map(as_enable)
+            .filter_map(|enable| {
+                enable
+                    .named_children(&mut enable.walk())
+                    .find(|n| n.kind() == "ident")
+                    .map(|n| self.node_text(&n))
+                    .filter(|name| *name != group.name)
+                    .and_then(|name| self.inline_candidate(enable, name))
+            })
+            .map(|target| {
+                let title = format!("Inline group `{}` into `{}`", group.name, target.name);
+                match self.inline_group(&group, &target) {
+                    Ok(edit) => lspt::CodeAction {
+                        title,
+                        kind: Some(lspt::CodeActionKind::REFACTOR_INLINE),
+                        edit: Some(edit),
+                        ..Default::default()
+                    },
+                    Err(reason) => lspt::CodeAction {
+                        title,
+                        kind: Some(lspt::CodeActionKind::REFACTOR_INLINE),
+                        disabled: Some(lspt::CodeActionDisabled { reason }),
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Find the definition and enable site of `name` if it is used from exactly one
+    /// control location and nowhere else.
+    fn inline_candidate<'a>(
+        &'a self,
+        node: ts::Node<'a>,
+        name: &str,
+    ) -> Option<InlineCandidate<'a>> {
+        let def = self.enclosing_group_def(node, name)?;
+        let name = name.to_string();
+        let enables: Vec<_> = self.enclosing_enables(node, &name).collect();
+        let hole_uses = def
+            .parent_until(|n| n.kind() == "component")
+            .into_iter()
+            .flat_map(|comp| comp.descendants())
+            .filter(|n| n.kind() == "hole" || n.kind() == "port_with")
+            .filter(|n| n.start_byte() < def.start_byte() || n.end_byte() > def.end_byte())
+            .any(|n| {
+                n.named_children(&mut n.walk())
+                    .any(|id| id.kind() == "ident" && self.node_text(&id) == name)
+            });
+        if enables.len() == 1 && !hole_uses {
+            Some(InlineCandidate {
+                name,
+                def,
+                enable: enables[0],
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Move the assignments of `group` into `target` and remove `group`. Returns
+    /// the reason why this would change the meaning of the program instead when
+    /// it is not safe to do so.
+    fn inline_group(
+        &self,
+        group: &InlineCandidate,
+        target: &InlineCandidate,
+    ) -> Result<lspt::WorkspaceEdit, String> {
+        for cand in [group, target] {
+            if cand.def.find_token("comb").is_some() {
+                return Err(format!("`{}` is a comb group", cand.name));
+            }
+        }
+        let is_static = |cand: &InlineCandidate| cand.def.find_token("static").is_some();
+        if is_static(group) != is_static(target) {
+            return Err("Only one of the groups is static".to_string());
+        }
+        // static groups run for a fixed number of cycles, so the merged group
+        // only behaves the same when both run side by side for equally long
+        if is_static(group) {
+            match (
+                self.group_latency(group.def),
+                self.group_latency(target.def),
+            ) {
+                (Some(a), Some(b)) if a != b => {
+                    return Err(format!(
+                        "latencies differ: `{}` takes {a} cycles and `{}` takes {b}",
+                        group.name, target.name
+                    ));
+                }
+                (Some(_), Some(_)) => (),
+                _ => return Err("the latency of a static group is unknown".to_string()),
+            }
+            if block_kind(group.enable) != Some("par") {
+                return Err("static groups can only be merged inside of a `par`".to_string());
+            }
+        }
+
+        let group_asgns = self.assignments(group.def);
+        let target_asgns = self.assignments(target.def);
+
+        // the done conditions must agree, otherwise the merged group finishes
+        // at a different time than one of the originals did
+        let done = |cand: &InlineCandidate, asgns: &[Assignment]| {
+            let hole = format!("{}[done]", cand.name);
+            asgns
+                .iter()
+                .find(|a| strip_ws(a.dest_text(self)) == hole)
+                .map(|a| (a.guard_text(self).map(strip_ws), strip_ws(a.src_text(self))))
+        };
+        if !is_static(group) {
+            match (done(group, &group_asgns), done(target, &target_asgns)) {
+                (None, _) => return Err(format!("`{0}` never assigns `{0}[done]`", group.name)),
+                (_, None) => return Err(format!("`{0}` never assigns `{0}[done]`", target.name)),
+                (Some(a), Some(b)) if a != b => {
+                    return Err(format!(
+                        "done conditions conflict: `{}[done]` and `{}[done]` differ",
+                        group.name, target.name
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        // the inlined group may not refer to its own holes anywhere else
+        let own_hole = format!("{}[", group.name);
+        let moved: Vec<_> = group_asgns
+            .iter()
+            .filter(|a| !strip_ws(a.dest_text(self)).starts_with(&own_hole))
+            .collect();
+        if moved
+            .iter()
+            .any(|a| self.node_text(&a.node).replace(' ', "").contains(&own_hole))
+        {
+            return Err(format!("`{}` reads its own holes", group.name));
+        }
+
+        // both groups writing the same port would create conflicting drivers
+        let writes = |asgns: &[&Assignment]| -> HashSet<String> {
+            asgns.iter().map(|a| strip_ws(a.dest_text(self))).collect()
+        };
+        let target_refs: Vec<_> = target_asgns.iter().collect();
+        if let Some(port) = writes(&moved).intersection(&writes(&target_refs)).next() {
+            return Err(format!("both groups write to `{port}`"));
+        }
+
+        // in a `seq`, the second group may observe what the first one did. Merging
+        // them would make those reads happen in the same cycle as the writes.
+        if block_kind(group.enable) != Some("par") {
+            let cells = |asgns: &[&Assignment], reads: bool| -> HashSet<String> {
+                asgns
+                    .iter()
+                    .flat_map(|a| {
+                        if reads {
+                            a.reads()
+                        } else {
+                            a.dest_port().into_iter().collect()
+                        }
+                    })
+                    .filter_map(|port| self.port_cell(&port))
+                    .map(|cell| cell.to_string())
+                    .collect()
+            };
+            let shared = cells(&moved, false)
+                .intersection(&cells(&target_refs, true))
+                .chain(cells(&target_refs, false).intersection(&cells(&moved, true)))
+                .next()
+                .cloned();
+            if let Some(cell) = shared {
+                return Err(format!(
+                    "`{cell}` is written by one group and read by the other"
+                ));
+            }
+        }
+
+        let mut edits = vec![];
+        if let Some(close) = target
+            .def
+            .children(&mut target.def.walk())
+            .filter(|n| n.is_token("}"))
+            .last()
+        {
+            let indent = target_asgns
+                .first()
+                .map(|a| self.indentation(&a.node).to_string())
+                .unwrap_or_else(|| format!("{}  ", self.indentation(&close)));
+            let new_text: String = moved
+                .iter()
+                .map(|a| format!("{indent}{}\n", self.node_text(&a.node)))
+                .collect();
+            let at_line_start = self.indentation(&close).len() == close.start_position().column;
+            edits.push(if at_line_start {
+                let pos = Point::new(close.start_position().row, 0);
+                lspt::TextEdit::new(Range::new(pos.clone(), pos).into(), new_text)
+            } else {
+                let pos: Point = close.start_position().into();
+                lspt::TextEdit::new(
+                    Range::new(pos.clone(), pos).into(),
+                    moved
+                        .iter()
+                        .map(|a| format!("{} ", self.node_text(&a.node)))
+                        .collect(),
+                )
+            });
+        }
+        edits.push(lspt::TextEdit::new(
+            self.line_range(&group.def).into(),
+            String::new(),
+        ));
+        edits.push(lspt::TextEdit::new(
+            self.line_range(&statement_of(group.enable)).into(),
+            String::new(),
+        ));
+
+        Ok(lspt::WorkspaceEdit::new(HashMap::from([(
+            self.url.clone(),
+            edits,
+        )])))
+    }
+}
+
+/// The outermost node that consists of just this `enable`
+fn statement_of(enable: ts::Node) -> ts::Node {
+    let mut stmt = enable;
+    while let Some(parent) = stmt
+        .parent()
+        .filter(|p| p.named_child_count() == 1 && !p.has_child_token("{"))
+    {
+        stmt = parent;
+    }
+    stmt
+}
+
+/// The `enable` wrapped by a statement node, if that is all the statement is
+fn as_enable(stmt: ts::Node) -> Option<ts::Node> {
+    let mut node = stmt;
+    while node.kind() != "enable" {
+        node = node
+            .named_child(0)
+            .filter(|_| node.named_child_count() == 1)?;
+    }
+    Some(node)
+}
+
+/// Whether the statement `node` is directly inside of a `seq` or `par` block
+fn block_kind(node: ts::Node) -> Option<&'static str> {
+    let block = statement_of(node).parent_until(|n| n.has_child_token("{"))?;
+    [Some(block), block.parent()]
+        .into_iter()
+        .flatten()
+        .find_map(|n| {
+            let mut cursor = n.walk();
+            let kind = n
+                .children(&mut cursor)
+                .find(|c| c.is_token("seq") || c.is_token("par"))
+                .map(|c| if c.kind() == "par" { "par" } else { "seq" });
+            kind
+        })
+}
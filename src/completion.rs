@@ -1,12 +1,19 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::PathBuf;
+use std::sync::LazyLock;
 
 use itertools::{multizip, Itertools};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types as lspt;
 
 use crate::{
-    convert::Point,
-    document::{Context, Document},
+    attributes::{self, AttrPosition},
+    convert::{Point, Range},
+    document::{ComponentSig, Context, Document},
     query_result::QueryResult2,
+    ts_utils::ParentUntil,
     Config,
 };
 
@@ -14,7 +21,23 @@ use crate::{
 pub struct CompletionItem {
     label: String,
     detail: String,
+    kind: Option<lspt::CompletionItemKind>,
+    documentation: Option<String>,
     snippet: Option<String>,
+    edit: Option<lspt::TextEdit>,
+    /// `Some(true)` sorts before items without a priority, `Some(false)` after
+    priority: Option<bool>,
+    additional_edits: Vec<lspt::TextEdit>,
+    /// Where to find the definition this item refers to, so its documentation
+    /// can be filled in by `completionItem/resolve`
+    data: Option<ResolveData>,
+}
+
+/// The definition of a component or primitive named `name` in the file at `url`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolveData {
+    pub url: lspt::Url,
+    pub name: String,
 }
 
 impl CompletionItem {
@@ -26,7 +49,13 @@ impl CompletionItem {
         CompletionItem {
             label: label.to_string(),
             detail: detail.to_string(),
+            kind: None,
+            documentation: None,
             snippet: None,
+            edit: None,
+            priority: None,
+            additional_edits: vec![],
+            data: None,
         }
     }
 
@@ -37,47 +66,131 @@ impl CompletionItem {
         S: ToString,
     {
         Self {
-            label: label.to_string(),
-            detail: detail.to_string(),
             snippet: Some(snippet.to_string()),
+            ..Self::simple(label, detail)
+        }
+    }
+
+    /// An item that replaces `range` with its label
+    fn replace<L, D>(label: L, detail: D, range: Range) -> Self
+    where
+        L: ToString,
+        D: ToString,
+    {
+        Self {
+            edit: Some(lspt::TextEdit::new(range.into(), label.to_string())),
+            ..Self::simple(label, detail)
+        }
+    }
+
+    /// An item that only tells the user something: accepting it inserts nothing
+    fn note<L, D>(label: L, detail: D, point: &Point) -> Self
+    where
+        L: ToString,
+        D: ToString,
+    {
+        Self {
+            edit: Some(lspt::TextEdit::new(
+                Range::new(point.clone(), point.clone()).into(),
+                String::new(),
+            )),
+            ..Self::simple(label, detail)
         }
     }
+
+    fn kind(mut self, kind: lspt::CompletionItemKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Markdown shown next to the item
+    fn documentation<D: ToString>(mut self, doc: D) -> Self {
+        self.documentation = Some(doc.to_string());
+        self
+    }
+
+    /// Look up the documentation of the definition `name` in `url` once the
+    /// item is selected
+    fn resolve_from(mut self, url: &lspt::Url, name: &str) -> Self {
+        self.data = Some(ResolveData {
+            url: url.clone(),
+            name: name.to_string(),
+        });
+        self
+    }
+
+    /// Also insert `edit` when accepting this item
+    fn with_edit(mut self, edit: lspt::TextEdit) -> Self {
+        self.additional_edits.push(edit);
+        self
+    }
+
+    /// Sort this item before all items that are not `first`
+    fn prioritize(mut self, first: bool) -> Self {
+        self.priority = Some(first);
+        self
+    }
+
+    /// Items are sorted by priority, then by kind so that the things that can
+    /// be written at the cursor come before keywords and snippets, then by
+    /// label.
+    fn sort_text(&self) -> String {
+        let priority = match self.priority {
+            Some(true) => 0,
+            None => 1,
+            Some(false) => 2,
+        };
+        let rank = match self.kind {
+            Some(lspt::CompletionItemKind::FIELD) => 0,
+            Some(lspt::CompletionItemKind::VARIABLE) => 1,
+            Some(lspt::CompletionItemKind::EVENT) => 2,
+            Some(lspt::CompletionItemKind::MODULE) => 3,
+            Some(lspt::CompletionItemKind::PROPERTY) => 4,
+            Some(lspt::CompletionItemKind::SNIPPET) => 5,
+            _ => 6,
+        };
+        format!("{priority}{rank}{}", self.label)
+    }
 }
 
 impl Into<lspt::CompletionItem> for CompletionItem {
     fn into(self) -> lspt::CompletionItem {
-        {
-            lspt::CompletionItem {
-                label: self.label,
-                detail: Some(self.detail),
-                insert_text: self.snippet,
-                insert_text_format: Some(lspt::InsertTextFormat::SNIPPET),
-                ..Default::default() // label_details: todo!(),
-                                     // kind: todo!(),
-                                     // documentation: todo!(),
-                                     // deprecated: todo!(),
-                                     // preselect: todo!(),
-                                     // sort_text: todo!(),
-                                     // filter_text: todo!(),
-                                     // insert_text: todo!(),
-                                     // insert_text_mode: todo!(),
-                                     // text_edit: todo!(),
-                                     // additional_text_edits: todo!(),
-                                     // command: todo!(),
-                                     // commit_characters: todo!(),
-                                     // data: todo!(),
-                                     // tags: todo!(),
-            }
-            // lspt::CompletionItem::new_simple(name.to_string(), descr.to_string())
+        lspt::CompletionItem {
+            sort_text: Some(self.sort_text()),
+            filter_text: Some(self.label.clone()),
+            label: self.label,
+            detail: Some(self.detail),
+            kind: self.kind,
+            documentation: self.documentation.map(markdown),
+            insert_text_format: Some(if self.snippet.is_some() {
+                lspt::InsertTextFormat::SNIPPET
+            } else {
+                lspt::InsertTextFormat::PLAIN_TEXT
+            }),
+            insert_text: self.snippet,
+            text_edit: self.edit.map(lspt::CompletionTextEdit::Edit),
+            additional_text_edits: Some(self.additional_edits).filter(|edits| !edits.is_empty()),
+            data: self.data.and_then(|data| serde_json::to_value(data).ok()),
+            ..Default::default()
         }
     }
 }
 
+fn markdown(value: String) -> lspt::Documentation {
+    lspt::Documentation::MarkupContent(lspt::MarkupContent {
+        kind: lspt::MarkupKind::Markdown,
+        value,
+    })
+}
+
 #[derive(Clone, Debug)]
 pub enum CompletionRes {
     Found(Vec<CompletionItem>),
     ContinuePort(Vec<PathBuf>, String),
     ContinueComponent(Vec<PathBuf>, Vec<CompletionItem>),
+    /// Library files that are not imported yet, with the string used to import
+    /// them and the place to insert that import
+    ContinueLibrary(Vec<(PathBuf, String)>, Vec<CompletionItem>, Point),
 }
 
 impl QueryResult2 for CompletionRes {
@@ -87,7 +200,9 @@ impl QueryResult2 for CompletionRes {
     fn found(&self) -> Option<Self::Data> {
         match self {
             CompletionRes::Found(data) => Some(data.clone()),
-            CompletionRes::ContinuePort(..) | CompletionRes::ContinueComponent(..) => None,
+            CompletionRes::ContinuePort(..)
+            | CompletionRes::ContinueComponent(..)
+            | CompletionRes::ContinueLibrary(..) => None,
         }
     }
 
@@ -96,6 +211,9 @@ impl QueryResult2 for CompletionRes {
             CompletionRes::Found(_) => vec![],
             CompletionRes::ContinuePort(paths, _) => paths.clone(),
             CompletionRes::ContinueComponent(paths, _) => paths.clone(),
+            CompletionRes::ContinueLibrary(files, ..) => {
+                files.iter().map(|(path, _)| path.clone()).collect()
+            }
         }
     }
 
@@ -111,7 +229,10 @@ impl QueryResult2 for CompletionRes {
                             .iter()
                             .map(|inp| (inp, "input"))
                             .chain(sig.outputs.iter().map(|out| (out, "output")))
-                            .map(|(name, descr)| CompletionItem::simple(name, descr))
+                            .map(|(name, descr)| {
+                                CompletionItem::simple(name, descr)
+                                    .kind(lspt::CompletionItemKind::FIELD)
+                            })
                             .collect(),
                     )
                 })
@@ -124,35 +245,10 @@ impl QueryResult2 for CompletionRes {
             CompletionRes::ContinueComponent(paths, compls) => {
                 let mut imports = doc.resolved_imports(config).collect::<Vec<_>>();
                 let paths = &paths[1..];
-                let comps_here = doc
-                    .root_node()
-                    .map(|root| {
-                        let prims =
-                            doc.captures(root, "(primitive (ident) @name (params (ident) @param))");
-                        let comps = doc.captures(root, "(component (ident) @name)");
-                        multizip((prims["name"].iter(), prims["param"].iter()))
-                            .map(|(n, p)| (doc.node_text(n), doc.node_text(p)))
-                            .group_by(|(n, _)| n.to_string())
-                            .into_iter()
-                            .map(|(n, p)| {
-                                CompletionItem::snippet(
-                                    n.to_string(),
-                                    "primitive",
-                                    format!(
-                                        "{n}({});",
-                                        p.enumerate()
-                                            .map(|(i, (_, y))| format!("${{{}:{y}}}", i + 1))
-                                            .join(", ")
-                                    ),
-                                )
-                            })
-                            .chain(compls.clone().into_iter())
-                            .chain(comps["name"].iter().map(|n| doc.node_text(n)).map(|n| {
-                                CompletionItem::snippet(n, "component", format!("{n}();"))
-                            }))
-                            .collect()
-                    })
-                    .unwrap_or(vec![]);
+                let comps_here = instantiation_items(doc)
+                    .into_iter()
+                    .chain(compls.clone())
+                    .collect::<Vec<_>>();
                 Some(if paths.is_empty() && imports.is_empty() {
                     Self::Found(comps_here)
                 } else {
@@ -160,6 +256,28 @@ impl QueryResult2 for CompletionRes {
                     Self::ContinueComponent(imports, comps_here)
                 })
             }
+            CompletionRes::ContinueLibrary(files, compls, import_point) => {
+                let path = doc.url.to_file_path().ok()?;
+                let pos = files.iter().position(|(p, _)| p == &path)?;
+                let import = lspt::TextEdit::new(
+                    Range::new(import_point.clone(), import_point.clone()).into(),
+                    format!("import \"{}\";\n", files[pos].1),
+                );
+                let compls = compls
+                    .iter()
+                    .cloned()
+                    .chain(instantiation_items(doc).into_iter().map(|item| {
+                        let detail = format!("{} from {}", item.detail, files[pos].1);
+                        CompletionItem { detail, ..item }.with_edit(import.clone())
+                    }))
+                    .collect::<Vec<_>>();
+                let rest = files[pos + 1..].to_vec();
+                Some(if rest.is_empty() {
+                    Self::Found(compls)
+                } else {
+                    Self::ContinueLibrary(rest, compls, import_point.clone())
+                })
+            }
         }
     }
 }
@@ -170,6 +288,7 @@ pub trait CompletionProvider {
         trigger_char: Option<&str>,
         point: &Point,
         config: &Config,
+        sigs: &HashMap<String, ComponentSig>,
     ) -> Option<Vec<CompletionRes>>;
 }
 
@@ -179,96 +298,686 @@ impl CompletionProvider for Document {
         trigger_char: Option<&str>,
         point: &Point,
         config: &Config,
+        sigs: &HashMap<String, ComponentSig>,
     ) -> Option<Vec<CompletionRes>> {
+        if let Some(attrs) = self.complete_attribute(point) {
+            return Some(vec![CompletionRes::Found(attrs)]);
+        }
+        if let Some(paths) = self.complete_import_path(point, config) {
+            return Some(vec![CompletionRes::Found(paths)]);
+        }
+
         self.last_word_from_point(point).and_then(|word| {
-            self.node_at_point(&point).and_then(|node| {
-                match (self.context_at_point(&point), trigger_char.as_deref()) {
-                    (Context::Toplevel, _) => {
-                        Some(vec![CompletionRes::Found(vec![CompletionItem::snippet(
-                            "component",
-                            "block",
-                            "component $1($2) -> ($3) {\n  cells {}\n  wires {}\n  control {}\n}",
-                        )])])
-                    }
-                    (Context::Component, _) => None,
-                    (Context::Cells, _) => Some(vec![
-                        CompletionRes::Found(
-                            self.components
-                                .keys()
-                                .map(|k| CompletionItem::snippet(k, "component", format!("{k}();")))
-                                .collect(),
-                        ),
-                        CompletionRes::ContinueComponent(
-                            self.resolved_imports(config).collect(),
-                            vec![],
-                        ),
-                    ]),
-                    (Context::Group, Some(".")) | (Context::Wires, Some(".")) => self
-                        .enclosing_component_name(node)
+            match (self.context_at_point(&point), trigger_char.as_deref()) {
+                (Context::Toplevel, _) => {
+                    Some(vec![CompletionRes::Found(vec![CompletionItem::snippet(
+                        "component",
+                        "block",
+                        "component $1($2) -> ($3) {\n  cells {}\n  wires {}\n  control {}\n}",
+                    )
+                    .kind(lspt::CompletionItemKind::SNIPPET)])])
+                }
+                (Context::Component, _) => None,
+                (Context::Cells, _) => Some(vec![
+                    CompletionRes::Found(
+                        self.components
+                            .keys()
+                            .map(|k| {
+                                CompletionItem::snippet(k, "component", format!("{k}();"))
+                                    .kind(lspt::CompletionItemKind::MODULE)
+                                    .resolve_from(&self.url, k)
+                            })
+                            .collect(),
+                    ),
+                    CompletionRes::ContinueComponent(
+                        self.resolved_imports(config).collect(),
+                        vec![],
+                    ),
+                    CompletionRes::ContinueLibrary(
+                        self.library_files(config),
+                        vec![],
+                        self.import_insert_point(),
+                    ),
+                ]),
+                (Context::Group, Some("[")) | (Context::Wires, Some("["))
+                    if self.line_prefix(point).is_some_and(|pre| {
+                        pre.ends_with(&format!("{word}[")) && !pre.ends_with("%[")
+                    }) =>
+                {
+                    self.component_at_point(point)
                         .and_then(|comp_name| self.components.get(&comp_name))
-                        .and_then(|ci| ci.cells.get(&word))
-                        .and_then(|cell_name| {
-                            self.components
-                                .get(cell_name)
-                                .map(|ci| {
-                                    vec![CompletionRes::Found(
-                                        ci.inputs
-                                            .iter()
-                                            .map(|i| CompletionItem::simple(i, "input"))
-                                            .chain(
-                                                ci.outputs
-                                                    .iter()
-                                                    .map(|o| CompletionItem::simple(o, "output")),
-                                            )
-                                            .collect(),
-                                    )]
+                        .map(|ci| {
+                            if ci.groups.contains(&word) {
+                                vec![CompletionRes::Found(vec![
+                                    CompletionItem::simple("go", "hole")
+                                        .kind(lspt::CompletionItemKind::EVENT),
+                                    CompletionItem::simple("done", "hole")
+                                        .kind(lspt::CompletionItemKind::EVENT),
+                                ])]
+                            } else {
+                                vec![CompletionRes::Found(vec![CompletionItem::note(
+                                    format!("`{word}` is not a group"),
+                                    "only groups have holes",
+                                    point,
+                                )])]
+                            }
+                        })
+                }
+                (Context::Group, Some(".")) | (Context::Wires, Some("."))
+                    if self.in_guard(point) =>
+                {
+                    self.component_at_point(point).map(|comp_name| {
+                        vec![CompletionRes::Found(self.one_bit_ports(
+                            &comp_name,
+                            sigs,
+                            Some(&word),
+                        ))]
+                    })
+                }
+                (Context::Group, Some(".")) | (Context::Wires, Some(".")) => {
+                    self.component_at_point(point).and_then(|comp_name| {
+                        let ci = self.components.get(&comp_name)?;
+                        let cell_comp = ci.cells.get(&word)?;
+                        let args = ci.cell_args.get(&word).map_or(&[][..], |a| &a[..]);
+                        let (side, width) = self.assignment_side(point, &comp_name, sigs);
+                        Some(match sigs.get(cell_comp) {
+                            Some(sig) => vec![CompletionRes::Found(complete_cell_ports(
+                                sig, args, side, width,
+                            ))],
+                            None => vec![CompletionRes::ContinuePort(
+                                self.resolved_imports(config).collect(),
+                                cell_comp.to_string(),
+                            )],
+                        })
+                    })
+                }
+                (Context::Group, _) | (Context::Wires, _) if self.in_guard(point) => {
+                    self.component_at_point(point).map(|comp_name| {
+                        let timing = self.static_latency_at(point).map(|latency| {
+                            CompletionItem::snippet(
+                                "%[start:end]",
+                                format!("timing guard: cycles 0 to {latency}"),
+                                format!("%[${{1:0}}:${{2:{latency}}}]"),
+                            )
+                            .kind(lspt::CompletionItemKind::SNIPPET)
+                        });
+                        vec![CompletionRes::Found(
+                            self.one_bit_ports(&comp_name, sigs, None)
+                                .into_iter()
+                                .chain(guard_operators())
+                                .chain(timing)
+                                .collect(),
+                        )]
+                    })
+                }
+                (Context::Group, _) | (Context::Wires, _) => {
+                    self.component_at_point(point).and_then(|comp_name| {
+                        let ci = self.components.get(&comp_name)?;
+                        let (side, width) = self.assignment_side(point, &comp_name, sigs);
+                        let holes = ci.groups.iter().map(move |g| {
+                            let snippet = match side {
+                                Side::Dest => format!("{g}[$1]"),
+                                Side::Src => format!("{g}[done]"),
+                            };
+                            CompletionItem::snippet(g, "hole", snippet)
+                                .kind(lspt::CompletionItemKind::EVENT)
+                        });
+                        Some(vec![CompletionRes::Found(
+                            ci.cells
+                                .iter()
+                                .map(|(g, comp)| {
+                                    CompletionItem::simple(g, format!("cell: {comp}"))
+                                        .kind(lspt::CompletionItemKind::VARIABLE)
                                 })
-                                .or_else(|| {
-                                    Some(vec![CompletionRes::ContinuePort(
-                                        self.resolved_imports(config).collect(),
-                                        cell_name.to_string(),
-                                    )])
+                                .map(|item| (item, None))
+                                .chain(holes.map(|item| (item, Some(1))))
+                                .chain(self.complete_self_ports(&comp_name, sigs, side))
+                                .map(|(item, w)| match (width, w) {
+                                    (Some(_), Some(_)) => item.prioritize(w == width),
+                                    _ => item,
                                 })
-                        }),
-                    (Context::Group, _) => self
-                        .enclosing_component_name(node)
-                        .and_then(|comp_name| self.components.get(&comp_name))
-                        .map(|ci| {
-                            vec![CompletionRes::Found(
-                                ci.cells
-                                    .keys()
-                                    .map(|g| CompletionItem::simple(g, "cell"))
-                                    .chain(ci.groups.iter().map(|g| {
-                                        CompletionItem::snippet(g, "hole", format!("{g}[$1]"))
-                                    }))
-                                    .collect(),
-                            )]
-                        }),
-                    (Context::Wires, _) => self
-                        .enclosing_component_name(node)
-                        .and_then(|comp_name| self.components.get(&comp_name))
-                        .map(|ci| {
-                            vec![CompletionRes::Found(
-                                ci.cells
-                                    .keys()
-                                    .map(|g| CompletionItem::simple(g, "cell"))
-                                    .collect(),
-                            )]
-                        }),
-
-                    (Context::Control, _) => self
-                        .enclosing_component_name(node)
-                        .and_then(|comp_name| self.components.get(&comp_name))
-                        .map(|ci| {
-                            vec![CompletionRes::Found(
+                                .chain(
+                                    width
+                                        .filter(|_| side == Side::Src)
+                                        .into_iter()
+                                        .flat_map(constants),
+                                )
+                                .collect(),
+                        )])
+                    })
+                }
+                (Context::Control, trigger) => {
+                    self.component_at_point(point).and_then(|comp_name| {
+                        let ci = self.components.get(&comp_name)?;
+                        let (before, _) = self.statement_at(point)?;
+                        static WITH_RE: LazyLock<Regex> = LazyLock::new(|| {
+                            Regex::new(r"\b(if|while)\s+[\w.]+\s+with\s+\w*$").unwrap()
+                        });
+                        static COND_RE: LazyLock<Regex> =
+                            LazyLock::new(|| Regex::new(r"\b(if|while)\s+[\w.]*$").unwrap());
+                        Some(vec![CompletionRes::Found(
+                            if let Some(items) = self.complete_invoke(before, &comp_name, sigs) {
+                                items
+                            } else if WITH_RE.is_match(before) {
+                                ci.comb_groups
+                                    .iter()
+                                    .map(|g| {
+                                        CompletionItem::simple(g, "comb group")
+                                            .kind(lspt::CompletionItemKind::EVENT)
+                                    })
+                                    .collect()
+                            } else if COND_RE.is_match(before) {
+                                let cell = Some(word.as_str()).filter(|_| trigger == Some("."));
+                                self.one_bit_ports(&comp_name, sigs, cell)
+                            } else {
                                 ci.groups
                                     .iter()
-                                    .map(|g| CompletionItem::simple(g, "group"))
-                                    .collect(),
-                            )]
-                        }),
+                                    .filter(|g| !ci.comb_groups.contains(g))
+                                    .map(|g| {
+                                        CompletionItem::simple(g, "group")
+                                            .kind(lspt::CompletionItemKind::EVENT)
+                                    })
+                                    .chain(control_snippets())
+                                    .collect()
+                            },
+                        )])
+                    })
                 }
+            }
+        })
+    }
+}
+
+impl Document {
+    /// The signature ports of the component `comp_name`, which are referred
+    /// to without a cell name. Only the ports that can be used on `side` of an
+    /// assignment are returned, together with their width.
+    fn complete_self_ports<'a>(
+        &'a self,
+        comp_name: &'a str,
+        sigs: &'a HashMap<String, ComponentSig>,
+        side: Side,
+    ) -> impl Iterator<Item = (CompletionItem, Option<u64>)> + 'a {
+        // a component writes its own outputs, and reads its own inputs
+        let direction = match side {
+            Side::Dest => "output",
+            Side::Src => "input",
+        };
+        sigs.get(comp_name).into_iter().flat_map(move |sig| {
+            let ports = match side {
+                Side::Dest => &sig.outputs,
+                Side::Src => &sig.inputs,
+            };
+            ports.iter().map(move |name| {
+                let detail = match sig.widths.get(name) {
+                    Some(width) => format!("{direction}: {width}"),
+                    None => direction.to_string(),
+                };
+                (
+                    CompletionItem::simple(name, detail).kind(lspt::CompletionItemKind::FIELD),
+                    sig.port_width(name, &[]),
+                )
             })
         })
     }
+
+    /// Readable 1-bit ports of the component `comp_name`: the outputs of its
+    /// cells, and its own inputs. When `cell` is given, only the ports of that
+    /// cell are returned, without the cell name in front.
+    fn one_bit_ports(
+        &self,
+        comp_name: &str,
+        sigs: &HashMap<String, ComponentSig>,
+        cell: Option<&str>,
+    ) -> Vec<CompletionItem> {
+        let Some(ci) = self.components.get(comp_name) else {
+            return vec![];
+        };
+        let cell_ports = ci
+            .cells
+            .iter()
+            .filter(|(name, _)| cell.map_or(true, |c| c == name.as_str()))
+            .filter_map(|(name, comp)| sigs.get(comp).map(|sig| (name, sig)))
+            .flat_map(|(name, sig)| {
+                let args = ci.cell_args.get(name).map_or(&[][..], |a| &a[..]);
+                sig.outputs
+                    .iter()
+                    .filter(move |port| sig.port_width(port, args) == Some(1))
+                    .map(move |port| match cell {
+                        Some(_) => CompletionItem::simple(port, "output: 1"),
+                        None => CompletionItem::simple(format!("{name}.{port}"), "output: 1"),
+                    })
+                    .map(|item| item.kind(lspt::CompletionItemKind::FIELD))
+            });
+        let self_ports = sigs
+            .get(comp_name)
+            .filter(|_| cell.is_none())
+            .into_iter()
+            .flat_map(|sig| {
+                sig.inputs
+                    .iter()
+                    .filter(|port| sig.port_width(port, &[]) == Some(1))
+                    .map(|port| {
+                        CompletionItem::simple(port, "input: 1")
+                            .kind(lspt::CompletionItemKind::FIELD)
+                    })
+            });
+        cell_ports.chain(self_ports).collect()
+    }
+
+    /// Complete the bindings of an `invoke` statement whose text up to the
+    /// cursor is `before`: `ref` cells in `[...]`, inputs in the first `(...)`
+    /// and outputs in the second. Ports that are already bound are skipped.
+    fn complete_invoke(
+        &self,
+        before: &str,
+        comp_name: &str,
+        sigs: &HashMap<String, ComponentSig>,
+    ) -> Option<Vec<CompletionItem>> {
+        static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\binvoke\s+(\w+)").unwrap());
+        let caps = RE.captures_iter(before).last()?;
+        let cell = caps.get(1)?;
+        let rest = &before[cell.end()..];
+
+        // find the binding list that is still open at the cursor
+        let mut open = None;
+        let mut paren_lists = 0;
+        let mut list_start = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '[' | '(' => {
+                    open = Some(c);
+                    list_start = i + 1;
+                }
+                ']' => open = None,
+                ')' => {
+                    open = None;
+                    paren_lists += 1;
+                }
+                _ => (),
+            }
+        }
+        let list = &rest[list_start..];
+        let bound: Vec<&str> = list
+            .split(',')
+            .filter_map(|binding| binding.split_once('=').map(|(name, _)| name.trim()))
+            .collect();
+        let current = list.rsplit(',').next().unwrap_or("");
+
+        let ci = self.components.get(comp_name)?;
+        let sig = sigs.get(ci.cells.get(cell.as_str())?)?;
+        let (names, detail, kind): (Vec<(&String, String)>, &str, _) = match (open?, paren_lists) {
+            // the value of a `ref` binding is one of our own cells
+            ('[', _) if current.contains('=') => {
+                return Some(
+                    ci.cells
+                        .iter()
+                        .map(|(name, comp)| {
+                            CompletionItem::simple(name, format!("cell: {comp}"))
+                                .kind(lspt::CompletionItemKind::VARIABLE)
+                        })
+                        .collect(),
+                )
+            }
+            ('[', _) => (
+                sig.ref_cells
+                    .iter()
+                    .map(|(name, comp)| (name, comp.to_string()))
+                    .collect(),
+                "ref cell",
+                lspt::CompletionItemKind::VARIABLE,
+            ),
+            // the value of an input binding can be a constant of the port's width
+            ('(', 0) if current.contains('=') => {
+                let port = current.split('=').next().unwrap_or("").trim();
+                let args = ci.cell_args.get(cell.as_str()).map_or(&[][..], |a| &a[..]);
+                return Some(sig.port_width(port, args).map_or(vec![], constants));
+            }
+            (_, _) if current.contains('=') => return Some(vec![]),
+            ('(', 0) => (
+                sig.inputs
+                    .iter()
+                    .map(|p| (p, sig.widths.get(p).cloned().unwrap_or_default()))
+                    .collect(),
+                "input",
+                lspt::CompletionItemKind::FIELD,
+            ),
+            ('(', 1) => (
+                sig.outputs
+                    .iter()
+                    .map(|p| (p, sig.widths.get(p).cloned().unwrap_or_default()))
+                    .collect(),
+                "output",
+                lspt::CompletionItemKind::FIELD,
+            ),
+            _ => return None,
+        };
+        Some(
+            names
+                .into_iter()
+                // the interface ports are connected by the compiler
+                .filter(|(name, _)| !["go", "done", "clk", "reset"].contains(&name.as_str()))
+                .filter(|(name, _)| !bound.contains(&name.as_str()))
+                .map(|(name, info)| {
+                    CompletionItem::snippet(
+                        name,
+                        format!("{detail}: {info}"),
+                        format!("{name} = $0"),
+                    )
+                    .kind(kind)
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether `point` is in the guard of an assignment: after the `=` and
+    /// before a `?`. A guard that is still being typed is recognized by the
+    /// operators in it, unless it ends in a comparison whose right side
+    /// should be completed like a source.
+    fn in_guard(&self, point: &Point) -> bool {
+        let Some((before, after)) = self.statement_at(point) else {
+            return false;
+        };
+        let Some(eq) = find_assign(before) else {
+            return false;
+        };
+        let rhs = &before[eq + 1..];
+        static CMP_RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(==|!=|<=|>=|<|>)\s*[\w.]*$").unwrap());
+        !rhs.contains('?')
+            && !CMP_RE.is_match(rhs)
+            && (after.contains('?') || rhs.contains(['!', '&', '|', '(']))
+    }
+
+    /// Which side of an assignment `point` is on, together with the width of
+    /// the other side if it is known. Guards count as sources, and inside a
+    /// comparison like `r.out == ` the other side is the compared port.
+    fn assignment_side(
+        &self,
+        point: &Point,
+        comp_name: &str,
+        sigs: &HashMap<String, ComponentSig>,
+    ) -> (Side, Option<u64>) {
+        let Some((before, after)) = self.statement_at(point) else {
+            return (Side::Dest, None);
+        };
+        match find_assign(before) {
+            Some(eq) => {
+                static CMP_RE: LazyLock<Regex> = LazyLock::new(|| {
+                    Regex::new(r"([\w.\[\]']+)\s*(==|!=|<=|>=|<|>)\s*[\w.]*$").unwrap()
+                });
+                let other = match CMP_RE.captures(&before[eq + 1..]) {
+                    Some(caps) => caps.get(1).unwrap().as_str(),
+                    None => &before[..eq],
+                };
+                (Side::Src, self.expr_width(comp_name, sigs, other))
+            }
+            None => (
+                Side::Dest,
+                find_assign(after).and_then(|eq| {
+                    let src = &after[eq + 1..];
+                    let src = src.rsplit_once('?').map_or(src, |(_, src)| src);
+                    self.expr_width(comp_name, sigs, src)
+                }),
+            ),
+        }
+    }
+
+    /// Complete the attributes that can be written at `point` when the cursor
+    /// is right after an `@`.
+    fn complete_attribute(&self, point: &Point) -> Option<Vec<CompletionItem>> {
+        static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"@\w*$").unwrap());
+        if !self.line_prefix(point).is_some_and(|pre| RE.is_match(pre)) {
+            return None;
+        }
+        let in_signature = self.node_at_point(point).is_some_and(|n| {
+            n.kind() == "signature" || n.parent_until_names(&["signature"]).is_some()
+        });
+        let pos = match self.context_at_point(point) {
+            _ if in_signature => AttrPosition::Port,
+            Context::Cells => AttrPosition::Cell,
+            Context::Wires => AttrPosition::Group,
+            Context::Control => AttrPosition::Control,
+            Context::Component | Context::Toplevel => AttrPosition::Component,
+            Context::Group => return Some(vec![]),
+        };
+        Some(
+            attributes::valid_at(pos)
+                .map(|attr| {
+                    let item = if attr.has_value {
+                        CompletionItem::snippet(
+                            attr.name,
+                            "attribute",
+                            format!("{}(${{1:1}})", attr.name),
+                        )
+                    } else {
+                        CompletionItem::simple(attr.name, "attribute")
+                    };
+                    item.kind(lspt::CompletionItemKind::PROPERTY)
+                        .documentation(attr.description)
+                })
+                .collect(),
+        )
+    }
+
+    /// Complete `.futil` files and directories inside of an `import "..."` string.
+    /// Only the path segment under the cursor is replaced.
+    fn complete_import_path(&self, point: &Point, config: &Config) -> Option<Vec<CompletionItem>> {
+        static RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#"^\s*import\s+"([^"]*)$"#).unwrap());
+        let typed = RE
+            .captures(self.line_prefix(point)?)?
+            .get(1)?
+            .as_str()
+            .to_string();
+        let (dir, segment) = typed.rsplit_once('/').unwrap_or(("", &typed));
+        let rest_of_segment = self
+            .line_suffix(point)
+            .map(|rest| rest.find(['/', '"']).unwrap_or(rest.len()))
+            .unwrap_or(0);
+        let range = Range::new(
+            Point::new(point.row(), point.column() - segment.len()),
+            Point::new(point.row(), point.column() + rest_of_segment),
+        );
+
+        let mut seen = HashSet::new();
+        Some(
+            self.search_paths(config)
+                .into_iter()
+                .filter_map(|base| fs::read_dir(base.join(dir)).ok().map(|rd| (base, rd)))
+                .flat_map(|(base, entries)| {
+                    entries.flatten().filter_map(move |entry| {
+                        let path = entry.path();
+                        let name = entry.file_name().to_string_lossy().to_string();
+                        if name.starts_with('.') {
+                            None
+                        } else if path.is_dir() {
+                            Some((
+                                format!("{name}/"),
+                                format!("directory in {}", base.display()),
+                            ))
+                        } else if path.extension().is_some_and(|ext| ext == "futil") {
+                            Some((name, format!("file in {}", base.display())))
+                        } else {
+                            None
+                        }
+                    })
+                })
+                .filter(|(label, _)| seen.insert(label.clone()))
+                .map(|(label, detail)| {
+                    let kind = if label.ends_with('/') {
+                        lspt::CompletionItemKind::FOLDER
+                    } else {
+                        lspt::CompletionItemKind::FILE
+                    };
+                    CompletionItem::replace(label, detail, range.clone()).kind(kind)
+                })
+                .collect(),
+        )
+    }
+
+    /// Documentation for the component or primitive `name` defined in this
+    /// file: its signature, followed by the comments written above it.
+    pub fn definition_docs(&self, name: &str) -> Option<lspt::Documentation> {
+        let (header, def) = self.definition_header(name)?;
+        let mut value = format!("```calyx\n{header}\n```");
+        if let Some(comment) = self.doc_comment(&def) {
+            value.push_str("\n\n");
+            value.push_str(&comment);
+        }
+        Some(markdown(value))
+    }
+}
+
+/// Completions for instantiating the components and primitives defined in `doc`
+fn instantiation_items(doc: &Document) -> Vec<CompletionItem> {
+    doc.root_node()
+        .map(|root| {
+            let prims = doc.captures(root, "(primitive (ident) @name (params (ident) @param))");
+            let comps = doc.captures(root, "(component (ident) @name)");
+            multizip((prims["name"].iter(), prims["param"].iter()))
+                .map(|(n, p)| (doc.node_text(n), doc.node_text(p)))
+                .group_by(|(n, _)| n.to_string())
+                .into_iter()
+                .map(|(n, p)| {
+                    CompletionItem::snippet(
+                        n.to_string(),
+                        "primitive",
+                        format!(
+                            "{n}({});",
+                            p.enumerate()
+                                .map(|(i, (_, y))| format!("${{{}:{y}}}", i + 1))
+                                .join(", ")
+                        ),
+                    )
+                    .kind(lspt::CompletionItemKind::MODULE)
+                    .resolve_from(&doc.url, &n)
+                })
+                .chain(comps["name"].iter().map(|n| doc.node_text(n)).map(|n| {
+                    CompletionItem::snippet(n, "component", format!("{n}();"))
+                        .kind(lspt::CompletionItemKind::MODULE)
+                        .resolve_from(&doc.url, n)
+                }))
+                .collect()
+        })
+        .unwrap_or(vec![])
+}
+
+/// The two sides of an assignment
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Dest,
+    Src,
+}
+
+/// The byte offset of the `=` of an assignment in `text`, skipping comparisons
+/// like `==` and `<=`.
+fn find_assign(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && !(i > 0 && b"=!<>".contains(&bytes[i - 1]))
+            && bytes.get(i + 1) != Some(&b'=')
+    })
+}
+
+/// The ports of a cell that can be used on `side` of an assignment. Ports with
+/// the same width as the other side are sorted first.
+fn complete_cell_ports(
+    sig: &ComponentSig,
+    args: &[String],
+    side: Side,
+    width: Option<u64>,
+) -> Vec<CompletionItem> {
+    // the inputs of a cell are written, and its outputs are read
+    let (ports, direction) = match side {
+        Side::Dest => (&sig.inputs, "input"),
+        Side::Src => (&sig.outputs, "output"),
+    };
+    ports
+        .iter()
+        .map(|port| {
+            let port_width = sig.port_width(port, args);
+            let detail = match port_width {
+                Some(w) => format!("{direction}: {w}"),
+                None => direction.to_string(),
+            };
+            let item = CompletionItem::simple(port, detail).kind(lspt::CompletionItemKind::FIELD);
+            match (width, port_width) {
+                (Some(_), Some(_)) => item.prioritize(port_width == width),
+                _ => item,
+            }
+        })
+        .collect()
+}
+
+/// Operators that combine and compare ports in a guard
+fn guard_operators() -> impl Iterator<Item = CompletionItem> {
+    [
+        ("!", "not"),
+        ("&", "and"),
+        ("|", "or"),
+        ("==", "equal"),
+        ("!=", "not equal"),
+        ("<", "less than"),
+        (">", "greater than"),
+        ("<=", "less than or equal"),
+        (">=", "greater than or equal"),
+    ]
+    .into_iter()
+    .map(|(op, detail)| CompletionItem::simple(op, detail).kind(lspt::CompletionItemKind::OPERATOR))
+}
+
+/// Constants of the given width, like `32'd0`
+fn constants(width: u64) -> Vec<CompletionItem> {
+    [0, 1]
+        .into_iter()
+        .map(|value| {
+            CompletionItem::simple(format!("{width}'d{value}"), format!("constant: {width}"))
+                .kind(lspt::CompletionItemKind::CONSTANT)
+                .prioritize(true)
+        })
+        .collect()
+}
+
+/// Snippets for every control statement
+fn control_snippets() -> impl Iterator<Item = CompletionItem> {
+    [
+        ("seq", "seq {\n  $0\n}"),
+        ("par", "par {\n  $0\n}"),
+        (
+            "if",
+            "if ${1:port} with ${2:cond_group} {\n  $3\n} else {\n  $0\n}",
+        ),
+        ("while", "while ${1:port} with ${2:cond_group} {\n  $0\n}"),
+        ("repeat", "repeat ${1:N} {\n  $0\n}"),
+        (
+            "invoke",
+            "invoke ${1:cell}(${2:in_port = src})(${3:out_port = dest});",
+        ),
+        ("static seq", "static seq {\n  $0\n}"),
+        ("static par", "static par {\n  $0\n}"),
+        ("static repeat", "static repeat ${1:N} {\n  $0\n}"),
+    ]
+    .into_iter()
+    .map(|(label, snippet)| {
+        CompletionItem::snippet(label, "control", snippet).kind(lspt::CompletionItemKind::SNIPPET)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_assign_skips_comparisons() {
+        assert_eq!(find_assign("r.in = a.out;"), Some(5));
+        assert_eq!(find_assign("r.in = a.out == b.out ? c.out;"), Some(5));
+        assert_eq!(
+            find_assign("r.write_en = !(a.out != b.out) ? 1'd1;"),
+            Some(11)
+        );
+        assert_eq!(find_assign("a.out <= b.out & a.out >= c.out"), None);
+        assert_eq!(find_assign("r.in"), None);
+    }
 }
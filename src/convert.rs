@@ -68,7 +68,7 @@ impl PartialOrd<Point> for Point {
 }
 
 /// Crate local Range representing a region between two points
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Range {
     start: Point,
     end: Point,
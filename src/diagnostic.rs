@@ -1,14 +1,20 @@
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
 
 use calyx_frontend;
 use calyx_ir;
 use calyx_utils::ErrorExt;
+use regex::Regex;
 
 pub struct Diagnostic;
 
 #[derive(Debug)]
 pub struct CalyxError {
-    #[allow(unused)]
     pub file_name: String,
     pub pos_start: usize,
     pub pos_end: usize,
@@ -31,4 +37,242 @@ impl Diagnostic {
                 }]
             })
     }
+
+    /// Check `text`, the possibly unsaved contents of the file at `path`.
+    ///
+    /// The frontend stops at the first error, so when there is one every
+    /// component in `bodies` (the ranges of its sections, see
+    /// `Document::component_bodies`) is checked again on its own, with the
+    /// sections of the other components left empty. This finds an error in
+    /// each component instead of only in the first broken one.
+    ///
+    /// Imports are looked up in `search_paths` in order, like
+    /// `Document::resolve_import` does.
+    pub fn check_text(
+        path: &Path,
+        text: &str,
+        bodies: &[Vec<Range<usize>>],
+        search_paths: &[PathBuf],
+    ) -> Vec<CalyxError> {
+        let mut errors = Self::check_scratch(path, text, search_paths);
+        if errors.is_empty() || bodies.len() < 2 {
+            return errors;
+        }
+        for (i, _) in bodies.iter().enumerate() {
+            let others = bodies
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, ranges)| ranges.iter().cloned());
+            for err in Self::check_scratch(path, &blank(text, others), search_paths) {
+                if !errors.iter().any(|e| {
+                    e.file_name == err.file_name && e.pos_start == err.pos_start && e.msg == err.msg
+                }) {
+                    errors.push(err);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Run the frontend on `text` as if it was the contents of `path`.
+    ///
+    /// The frontend only reads files, so the text is written to a scratch file
+    /// first. Imports are replaced by the absolute path of the file they are
+    /// found at, `extern` paths by the absolute path next to `path`, and the
+    /// positions of errors in the scratch file are mapped back to `text`.
+    ///
+    /// The frontend only takes a single library path, which it uses for the
+    /// imports of imported files. It gets the search path that the first
+    /// library import was found in. Imports inside of imported files are only
+    /// looked up next to those files and in that one path, so an import there
+    /// that only resolves through one of the other library paths is reported
+    /// as missing.
+    fn check_scratch(path: &Path, text: &str, search_paths: &[PathBuf]) -> Vec<CalyxError> {
+        let (scratch_text, rewrites, lib_path) = absolute_paths(text, search_paths);
+        let scratch = scratch_path(path);
+        let written = scratch
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&scratch, scratch_text));
+        if written.is_err() {
+            return vec![];
+        }
+        let lib_path = lib_path
+            .or_else(|| search_paths.get(1))
+            .or_else(|| search_paths.first())
+            .cloned()
+            .unwrap_or_default();
+        let mut errors = Self::did_save(&scratch, &lib_path);
+        let _ = fs::remove_file(&scratch);
+        let _ = scratch.parent().map(fs::remove_dir);
+        for err in &mut errors {
+            if Path::new(&err.file_name) == scratch {
+                err.file_name = path.to_string_lossy().to_string();
+                err.pos_start = rewrites.original_offset(err.pos_start);
+                err.pos_end = rewrites.original_offset(err.pos_end);
+            }
+        }
+        errors
+    }
+}
+
+/// `text` with the given ranges replaced by spaces. Newlines are kept so that
+/// every other position stays where it was.
+fn blank(text: &str, ranges: impl Iterator<Item = Range<usize>>) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+    for range in ranges {
+        for b in &mut bytes[range] {
+            if *b != b'\n' {
+                *b = b' ';
+            }
+        }
+    }
+    // only whole characters between ASCII braces are replaced
+    String::from_utf8(bytes).unwrap_or_else(|_| text.to_string())
+}
+
+/// The import strings that were replaced in a text, as
+/// `(start in the original text, original length, new length)`
+struct Rewrites(Vec<(usize, usize, usize)>);
+
+impl Rewrites {
+    /// The offset in the original text of `offset` in the rewritten one.
+    /// Offsets inside of a replaced string map to its start.
+    fn original_offset(&self, offset: usize) -> usize {
+        let mut shift: isize = 0;
+        for &(start, old_len, new_len) in &self.0 {
+            let new_start = (start as isize + shift) as usize;
+            if offset < new_start {
+                break;
+            } else if offset < new_start + new_len {
+                return start;
+            }
+            shift += new_len as isize - old_len as isize;
+        }
+        (offset as isize - shift).max(0) as usize
+    }
+}
+
+/// `text` with every import replaced by the absolute path of the file it is
+/// found at in `search_paths`, together with the first of the search paths
+/// after the directory of the file itself that an import was found in.
+/// `extern` paths are relative to the directory of the file, which is the
+/// first of the search paths, and are made absolute as well.
+fn absolute_paths<'a>(
+    text: &str,
+    search_paths: &'a [PathBuf],
+) -> (String, Rewrites, Option<&'a PathBuf>) {
+    static RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"\b(import|extern)\s+"([^"]*)""#).unwrap());
+    let mut out = String::with_capacity(text.len());
+    let mut rewrites = vec![];
+    let mut lib_path = None;
+    let mut last = 0;
+    for caps in RE.captures_iter(text) {
+        let quoted = caps.get(2).unwrap();
+        let full = if &caps[1] == "extern" {
+            let Some(dir) = search_paths.first() else {
+                continue;
+            };
+            dir.join(quoted.as_str())
+        } else {
+            let Some((i, full)) = search_paths
+                .iter()
+                .map(|dir| dir.join(quoted.as_str()))
+                .enumerate()
+                .find(|(_, full)| full.exists())
+            else {
+                continue;
+            };
+            if i > 0 {
+                lib_path.get_or_insert(&search_paths[i]);
+            }
+            full
+        };
+        let full = full.to_string_lossy();
+        out.push_str(&text[last..quoted.start()]);
+        out.push_str(&full);
+        rewrites.push((quoted.start(), quoted.len(), full.len()));
+        last = quoted.end();
+    }
+    out.push_str(&text[last..]);
+    (out, Rewrites(rewrites), lib_path)
+}
+
+/// A new file in the temporary directory to check the contents of `path` in.
+/// It keeps the file name so that errors still read naturally. Every call gets
+/// its own directory, so that checks of the same file that run at the same
+/// time, like after a change and a save, do not overwrite each other.
+fn scratch_path(path: &Path) -> PathBuf {
+    static RUNS: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let name = path
+        .file_name()
+        .map_or("main.futil".into(), |n| n.to_string_lossy());
+    std::env::temp_dir()
+        .join(format!(
+            "calyx-lsp-{}-{:x}-{}",
+            std::process::id(),
+            hasher.finish(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ))
+        .join(name.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_offset_around_rewrite() {
+        // "abc" at 10 became 13 bytes long
+        let rewrites = Rewrites(vec![(10, 3, 13)]);
+        assert_eq!(rewrites.original_offset(4), 4);
+        assert_eq!(rewrites.original_offset(10), 10);
+        assert_eq!(rewrites.original_offset(15), 10);
+        assert_eq!(rewrites.original_offset(23), 13);
+        assert_eq!(rewrites.original_offset(30), 20);
+    }
+
+    #[test]
+    fn original_offset_after_several_rewrites() {
+        let rewrites = Rewrites(vec![(0, 2, 5), (10, 4, 1)]);
+        // the second string starts at 13 in the rewritten text
+        assert_eq!(rewrites.original_offset(12), 9);
+        assert_eq!(rewrites.original_offset(13), 10);
+        assert_eq!(rewrites.original_offset(14), 14);
+    }
+
+    #[test]
+    fn original_offset_of_absolute_paths() {
+        let text = "extern \"a.sv\" {\n  comp x() -> ();\n}\n";
+        let dirs = [PathBuf::from("/lib")];
+        let (scratch, rewrites, _) = absolute_paths(text, &dirs);
+        assert_eq!(scratch, "extern \"/lib/a.sv\" {\n  comp x() -> ();\n}\n");
+        let comp = scratch.find("comp").unwrap();
+        assert_eq!(rewrites.original_offset(comp), text.find("comp").unwrap());
+        let inside = scratch.find("a.sv").unwrap();
+        assert_eq!(rewrites.original_offset(inside), text.find("a.sv").unwrap());
+    }
+
+    #[test]
+    fn blank_keeps_positions() {
+        let text = "component a() -> () {\n  cells {}\n}\ncomponent b() -> () {}\n";
+        let start = text.find('{').unwrap() + 1;
+        let end = text.find("\n}").unwrap();
+        let blanked = blank(text, std::iter::once(start..end));
+        assert_eq!(blanked.len(), text.len());
+        assert_eq!(blanked.lines().count(), text.lines().count());
+        assert_eq!(&blanked[..start], &text[..start]);
+        assert!(blanked[start..end].chars().all(|c| c == ' ' || c == '\n'));
+        assert_eq!(&blanked[end..], &text[end..]);
+    }
+
+    #[test]
+    fn blank_nothing() {
+        let text = "import \"primitives/core.futil\";\n";
+        assert_eq!(blank(text, std::iter::empty()), text);
+    }
 }
@@ -1,7 +1,9 @@
 //! Represents a single Calyx file
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 
 use itertools::{multizip, Itertools};
 use regex::Regex;
@@ -11,7 +13,7 @@ use tree_sitter as ts;
 
 use crate::convert::{Contains, Point, Range};
 use crate::log;
-use crate::ts_utils::ParentUntil;
+use crate::ts_utils::{Descendants, ParentUntil};
 use crate::{tree_sitter_calyx, Config};
 
 pub struct Document {
@@ -21,22 +23,51 @@ pub struct Document {
     parser: ts::Parser,
     /// Map the stores information about every component defined in this file.
     pub components: HashMap<String, PrivateComponentInfo>,
+    /// The components and signatures of the last parse without syntax errors.
+    /// While the user is typing the tree is usually broken, and these fill in
+    /// whatever can no longer be found in it.
+    last_good: (
+        HashMap<String, PrivateComponentInfo>,
+        HashMap<String, ComponentSig>,
+    ),
 }
 
 /// File-private information about each component
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PrivateComponentInfo {
-    pub inputs: Vec<String>,
-    pub outputs: Vec<String>,
     pub cells: HashMap<String, String>,
+    /// The arguments each cell is instantiated with
+    pub cell_args: HashMap<String, Vec<String>>,
     pub groups: Vec<String>,
+    /// The subset of `groups` that are `comb` groups
+    pub comb_groups: Vec<String>,
 }
 
 /// Public information about a component
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ComponentSig {
     pub inputs: Vec<String>,
     pub outputs: Vec<String>,
+    /// Names of the parameters of a primitive, like `WIDTH`
+    pub params: Vec<String>,
+    /// The width of every port, as written in the signature
+    pub widths: HashMap<String, String>,
+    /// The `ref` cells of a component, which are bound when it is invoked
+    pub ref_cells: HashMap<String, String>,
+}
+
+impl ComponentSig {
+    /// The width of `port` on a cell instantiated with `args`
+    pub fn port_width(&self, port: &str, args: &[String]) -> Option<u64> {
+        let width = self.widths.get(port)?;
+        width.parse().ok().or_else(|| {
+            self.params
+                .iter()
+                .position(|p| p == width)
+                .and_then(|idx| args.get(idx))
+                .and_then(|arg| arg.parse().ok())
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -64,6 +95,81 @@ pub enum Context {
     Control,
 }
 
+/// A single `dest = guard ? src;` assignment inside of a group or `wires`.
+#[derive(Clone, Debug)]
+pub struct Assignment<'a> {
+    pub node: ts::Node<'a>,
+    /// The port or hole being written
+    pub dest: ts::Node<'a>,
+    eq: ts::Node<'a>,
+    question: Option<ts::Node<'a>>,
+}
+
+impl<'a> Assignment<'a> {
+    fn from_node(node: ts::Node<'a>) -> Option<Self> {
+        let eq = node.find_token("=")?;
+        let dest = node
+            .children(&mut node.walk())
+            .filter(|n| n.is_named() && n.end_byte() <= eq.start_byte())
+            .last()?;
+        let question = node
+            .find_token("?")
+            .filter(|q| q.start_byte() > eq.end_byte());
+        Some(Self {
+            node,
+            dest,
+            eq,
+            question,
+        })
+    }
+
+    /// Byte range of everything to the right of `=`, without the trailing `;`
+    fn rhs_bytes(&self, doc: &Document) -> std::ops::Range<usize> {
+        let end = doc.text[..self.node.end_byte()]
+            .trim_end()
+            .trim_end_matches(';')
+            .len();
+        self.eq.end_byte()..end.max(self.eq.end_byte())
+    }
+
+    /// The `port` or `hole` node being written
+    pub fn dest_port(&self) -> Option<ts::Node<'a>> {
+        std::iter::once(self.dest)
+            .chain(self.dest.descendants())
+            .find(|n| n.kind() == "port" || n.kind() == "hole")
+    }
+
+    pub fn dest_text<'d>(&self, doc: &'d Document) -> &'d str {
+        doc.node_text(&self.dest)
+    }
+
+    pub fn guard_text<'d>(&self, doc: &'d Document) -> Option<&'d str> {
+        self.question
+            .map(|q| doc.text[self.eq.end_byte()..q.start_byte()].trim())
+    }
+
+    pub fn src_text<'d>(&self, doc: &'d Document) -> &'d str {
+        let rhs = self.rhs_bytes(doc);
+        let start = self.question.map(|q| q.end_byte()).unwrap_or(rhs.start);
+        doc.text[start..rhs.end.max(start)].trim()
+    }
+
+    /// `port` and `hole` nodes read by this assignment, either in the guard or the source.
+    pub fn reads(&self) -> Vec<ts::Node<'a>> {
+        self.node
+            .descendants()
+            .into_iter()
+            .filter(|n| n.start_byte() >= self.eq.end_byte())
+            .filter(|n| {
+                (n.kind() == "port" || n.kind() == "hole")
+                    && !n
+                        .parent()
+                        .is_some_and(|p| p.kind() == "port" || p.kind() == "hole")
+            })
+            .collect()
+    }
+}
+
 pub trait NodeRangesIter<'a>: Iterator<Item = ts::Node<'a>> + Sized {
     fn ranges(self) -> impl Iterator<Item = Range> {
         self.map(|n| Range::from(n))
@@ -80,6 +186,7 @@ impl Document {
             tree: None,
             parser,
             components: HashMap::default(),
+            last_good: Default::default(),
         }
     }
 
@@ -93,6 +200,33 @@ impl Document {
         self.text = text.to_string();
         self.tree = self.parser.parse(text, None);
         self.update_component_map();
+        if self.has_syntax_errors() {
+            // keep what the broken tree lost from the last good one
+            for (name, good) in &self.last_good.0 {
+                let info = self
+                    .components
+                    .entry(name.clone())
+                    .or_insert_with(|| good.clone());
+                for (cell, comp) in &good.cells {
+                    info.cells.entry(cell.clone()).or_insert(comp.clone());
+                }
+                for (cell, args) in &good.cell_args {
+                    info.cell_args.entry(cell.clone()).or_insert(args.clone());
+                }
+                for group in &good.groups {
+                    if !info.groups.contains(group) {
+                        info.groups.push(group.clone());
+                    }
+                }
+                for group in &good.comb_groups {
+                    if !info.comb_groups.contains(group) {
+                        info.comb_groups.push(group.clone());
+                    }
+                }
+            }
+        } else {
+            self.last_good = (self.components.clone(), self.tree_signatures().collect());
+        }
         log::Debug::update("tree", self.tree.as_ref().unwrap().root_node().to_sexp())
     }
 
@@ -100,6 +234,11 @@ impl Document {
         self.tree.as_ref().map(|t| t.root_node())
     }
 
+    /// Whether the tree contains `ERROR` or `MISSING` nodes
+    pub fn has_syntax_errors(&self) -> bool {
+        self.root_node().is_some_and(|root| root.has_error())
+    }
+
     pub fn byte_to_point(&self, byte_offset: usize) -> Option<Point> {
         if byte_offset == 0 {
             Some(Point::zero())
@@ -158,52 +297,57 @@ impl Document {
                 let map = self.captures(
                     root,
                     r#"(component (ident) @comp
-                         (signature (io_port_list) @inputs
-                                    (io_port_list) @outputs)
                          (cells) @cells
                          (wires) @wires)"#,
                 );
-                multizip((
-                    map["comp"].iter(),
-                    map["inputs"].iter(),
-                    map["outputs"].iter(),
-                    map["cells"].iter(),
-                    map["wires"].iter(),
-                ))
-                .map(|(comp, inputs, outputs, cells, wires)| {
-                    (
-                        self.node_text(comp).to_string(),
-                        PrivateComponentInfo {
-                            inputs: self.captures(*inputs, "(ident) @id")["id"]
-                                .iter()
-                                .map(|n| self.node_text(n).to_string())
-                                .collect(),
-                            outputs: self.captures(*outputs, "(ident) @id")["id"]
-                                .iter()
-                                .map(|n| self.node_text(n).to_string())
-                                .collect(),
-                            cells: {
-                                let cells = self.captures(
+                multizip((map["comp"].iter(), map["cells"].iter(), map["wires"].iter()))
+                    .map(|(comp, cells, wires)| {
+                        (
+                            self.node_text(comp).to_string(),
+                            PrivateComponentInfo {
+                                cells: {
+                                    let cells = self.captures(
                                     *cells,
                                     "(cell_assignment (ident) @name (instantiation (ident) @cell))",
                                 );
-                                multizip((cells["name"].iter(), cells["cell"].iter()))
-                                    .map(|(name, cell)| {
-                                        (
-                                            self.node_text(name).to_string(),
-                                            self.node_text(cell).to_string(),
-                                        )
+                                    multizip((cells["name"].iter(), cells["cell"].iter()))
+                                        .map(|(name, cell)| {
+                                            (
+                                                self.node_text(name).to_string(),
+                                                self.node_text(cell).to_string(),
+                                            )
+                                        })
+                                        .collect()
+                                },
+                                cell_args: {
+                                    let cells = self.captures(
+                                        *cells,
+                                        "(cell_assignment (ident) @name (instantiation) @inst)",
+                                    );
+                                    multizip((cells["name"].iter(), cells["inst"].iter()))
+                                        .map(|(name, inst)| {
+                                            (
+                                                self.node_text(name).to_string(),
+                                                self.instantiation_args(inst),
+                                            )
+                                        })
+                                        .collect()
+                                },
+                                groups: self.captures(*wires, "(group (ident) @id)")["id"]
+                                    .iter()
+                                    .map(|n| self.node_text(n).to_string())
+                                    .collect(),
+                                comb_groups: self.captures(*wires, "(group (ident) @id)")["id"]
+                                    .iter()
+                                    .filter(|n| {
+                                        n.parent().is_some_and(|g| g.has_child_token("comb"))
                                     })
-                                    .collect()
+                                    .map(|n| self.node_text(n).to_string())
+                                    .collect(),
                             },
-                            groups: self.captures(*wires, "(group (ident) @id)")["id"]
-                                .iter()
-                                .map(|n| self.node_text(n).to_string())
-                                .collect(),
-                        },
-                    )
-                })
-                .collect_vec()
+                        )
+                    })
+                    .collect_vec()
             })
             .collect();
     }
@@ -215,6 +359,27 @@ impl Document {
         })
     }
 
+    /// For every component, the byte ranges between the braces of its
+    /// `cells`, `wires` and `control` sections
+    pub fn component_bodies(&self) -> Vec<Vec<std::ops::Range<usize>>> {
+        self.root_node()
+            .map(|root| self.captures(root, "(component) @comp")["comp"].clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|comp| {
+                let mut cursor = comp.walk();
+                comp.named_children(&mut cursor)
+                    .filter(|n| ["cells", "wires", "control"].contains(&n.kind()))
+                    .filter_map(|section| {
+                        let open = section.find_token("{")?;
+                        let close = section.end_byte().checked_sub(1)?;
+                        (self.text.as_bytes()[close] == b'}').then_some(open.end_byte()..close)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     pub fn enclosing_cells<'a>(&'a self, node: ts::Node<'a>) -> impl Iterator<Item = ts::Node<'a>> {
         node.parent_until(|n| n.kind() == "component")
             .into_iter()
@@ -246,6 +411,16 @@ impl Document {
             })
     }
 
+    /// The width written after the `io_port` identifier `port`, which is either
+    /// a number or a parameter name.
+    pub fn port_width(&self, port: &ts::Node) -> Option<&str> {
+        port.parent()
+            .filter(|p| p.kind() == "io_port")
+            .and_then(|p| p.named_children(&mut p.walk()).last())
+            .filter(|width| width != port)
+            .map(|width| self.node_text(&width))
+    }
+
     pub fn enclosing_component_name(&self, node: ts::Node) -> Option<String> {
         node.parent_until(|n| n.kind() == "component")
             .and_then(|comp_node| {
@@ -255,6 +430,55 @@ impl Document {
             })
     }
 
+    /// Every assignment below `node`. Cell instantiations are not assignments.
+    pub fn assignments<'a>(&'a self, node: ts::Node<'a>) -> Vec<Assignment<'a>> {
+        let mut cursor = node.walk();
+        node.children(&mut cursor)
+            .flat_map(|child| {
+                if child.kind() == "cell_assignment" {
+                    vec![]
+                } else if child.children(&mut child.walk()).any(|n| n.is_token("=")) {
+                    Assignment::from_node(child).into_iter().collect()
+                } else {
+                    self.assignments(child)
+                }
+            })
+            .collect()
+    }
+
+    /// The definition node of the group `name` in the component surrounding `node`
+    pub fn enclosing_group_def<'a>(
+        &'a self,
+        node: ts::Node<'a>,
+        name: &str,
+    ) -> Option<ts::Node<'a>> {
+        self.enclosing_groups(node)
+            .find(|g| self.node_text(g) == name)
+            .and_then(|g| g.parent())
+    }
+
+    /// Every `enable` statement of the group `name` in the component surrounding `node`
+    pub fn enclosing_enables<'a>(
+        &'a self,
+        node: ts::Node<'a>,
+        name: &str,
+    ) -> impl Iterator<Item = ts::Node<'a>> {
+        let name = name.to_string();
+        node.parent_until(|n| n.kind() == "component")
+            .into_iter()
+            .flat_map(|comp_node| {
+                self.captures(comp_node, "(enable (ident) @group)")["group"].clone()
+            })
+            .filter(move |n| self.node_text(n) == name)
+            .filter_map(|n| n.parent())
+    }
+
+    /// The latency of the group defined by `def`, if it is a `static<N>` group
+    pub fn group_latency(&self, def: ts::Node) -> Option<u64> {
+        let header = self.node_text(&def).split('{').next()?;
+        STATIC_GROUP.captures(header)?[1].parse().ok()
+    }
+
     /// Return the list of imported files
     pub fn raw_imports(&self) -> Vec<String> {
         self.tree
@@ -266,30 +490,93 @@ impl Document {
             .collect()
     }
 
+    /// The range of the `import` statement for the file `import`
+    pub fn import_range(&self, import: &str) -> Option<Range> {
+        let root = self.root_node()?;
+        self.captures(root, "(import (string) @file)")["file"]
+            .iter()
+            .find(|n| self.node_text(n).replace('"', "") == import)
+            .and_then(|n| n.parent())
+            .map(Range::from)
+    }
+
+    /// The file that `import` refers to, together with the search path it was
+    /// found in: the first one that contains it
+    pub fn resolve_import(&self, import: &str, config: &Config) -> Option<(PathBuf, PathBuf)> {
+        self.search_paths(config)
+            .into_iter()
+            .map(|dir| {
+                let file = dir.join(import).resolve().into_owned();
+                (dir, file)
+            })
+            .find(|(_, file)| file.exists())
+    }
+
+    /// The directories that imports are searched in, in order: the directory
+    /// of this file, followed by every configured library path. Files that are
+    /// not on disk, like unsaved buffers, only have the library paths.
+    pub fn search_paths(&self, config: &Config) -> Vec<PathBuf> {
+        let cur_dir = self
+            .url
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+        cur_dir.into_iter().chain(library_paths(config)).collect()
+    }
+
     pub fn resolved_imports<'a>(
         &'a self,
         config: &'a Config,
     ) -> impl Iterator<Item = PathBuf> + 'a {
-        let lib_paths = &config.calyx_lsp.library_paths;
-        let cur_dir = self
-            .url
-            .to_file_path()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .to_path_buf();
         self.raw_imports()
             .into_iter()
-            .cartesian_product(
-                vec![cur_dir]
-                    .into_iter()
-                    .chain(lib_paths.into_iter().map(|p| PathBuf::from(p))),
-            )
+            .cartesian_product(self.search_paths(config))
             .map(|(base_path, lib_path)| lib_path.join(base_path).resolve().into_owned())
             .filter(|p| p.exists())
     }
 
+    /// Every `.futil` file in the library paths that is not imported yet, with
+    /// the string it would be imported as.
+    pub fn library_files(&self, config: &Config) -> Vec<(PathBuf, String)> {
+        let imported: Vec<_> = self.resolved_imports(config).collect();
+        let cur_file = self.url.to_file_path().ok();
+        config
+            .library_files
+            .get_or_init(|| walk_library(config))
+            .iter()
+            .filter(|(file, _)| !imported.contains(file) && Some(file) != cur_file.as_ref())
+            .cloned()
+            .collect()
+    }
+
+    /// Where a new `import` statement should go: after the last one
+    pub fn import_insert_point(&self) -> Point {
+        self.root_node()
+            .and_then(|root| {
+                self.captures(root, "(import) @import")["import"]
+                    .iter()
+                    .map(|n| n.end_position().row + 1)
+                    .max()
+            })
+            .map_or(Point::zero(), |row| Point::new(row, 0))
+    }
+
+    /// The signature of every component and primitive in this file. When the
+    /// file does not parse, signatures that cannot be found in the current tree
+    /// come from the last tree that did.
     pub fn signatures(&self) -> impl Iterator<Item = (String, ComponentSig)> + '_ {
+        let current: Vec<_> = self.tree_signatures().collect();
+        let stale: Vec<_> = self
+            .last_good
+            .1
+            .iter()
+            .filter(|(name, _)| !current.iter().any(|(n, _)| n == *name))
+            .map(|(name, sig)| (name.clone(), sig.clone()))
+            .collect();
+        current.into_iter().chain(stale)
+    }
+
+    fn tree_signatures(&self) -> impl Iterator<Item = (String, ComponentSig)> + '_ {
         self.components()
             .filter_map(|comp_node| {
                 comp_node
@@ -306,23 +593,69 @@ impl Document {
                     map.remove("outputs").unwrap().into_iter(),
                 ))
                 .map(move |(inputs, outputs)| {
+                    let inputs = self.captures(inputs, "(io_port (ident) @id . (_))")["id"].clone();
+                    let outputs =
+                        self.captures(outputs, "(io_port (ident) @id . (_))")["id"].clone();
                     (
                         name.to_string(),
                         ComponentSig {
-                            inputs: self.captures(inputs, "(io_port (ident) @id . (_))")["id"]
+                            inputs: inputs
+                                .iter()
+                                .map(|n| self.node_text(n).to_string())
+                                .collect(),
+                            outputs: outputs
                                 .iter()
                                 .map(|n| self.node_text(n).to_string())
                                 .collect(),
-                            outputs: self.captures(outputs, "(io_port (ident) @id . (_))")["id"]
+                            params: self.captures(comp_node, "(params (ident) @param)")["param"]
                                 .iter()
                                 .map(|n| self.node_text(n).to_string())
                                 .collect(),
+                            widths: inputs
+                                .iter()
+                                .chain(outputs.iter())
+                                .filter_map(|n| {
+                                    self.port_width(n)
+                                        .map(|w| (self.node_text(n).to_string(), w.to_string()))
+                                })
+                                .collect(),
+                            ref_cells: {
+                                let cells = self.captures(
+                                    comp_node,
+                                    "(cell_assignment (ident) @name (instantiation (ident) @cell))",
+                                );
+                                multizip((cells["name"].iter(), cells["cell"].iter()))
+                                    .filter(|(name, _)| {
+                                        name.parent().is_some_and(|c| c.has_child_token("ref"))
+                                    })
+                                    .map(|(name, cell)| {
+                                        (
+                                            self.node_text(name).to_string(),
+                                            self.node_text(cell).to_string(),
+                                        )
+                                    })
+                                    .collect()
+                            },
                         },
                     )
                 })
             })
     }
 
+    /// The arguments of an instantiation like `std_reg(32)`
+    pub fn instantiation_args(&self, inst: &ts::Node) -> Vec<String> {
+        let text = self.node_text(inst);
+        text.split_once('(')
+            .and_then(|(_, args)| args.rsplit_once(')'))
+            .map(|(args, _)| {
+                args.split(',')
+                    .map(|a| a.trim().to_string())
+                    .filter(|a| !a.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     pub fn node_at_point(&self, point: &Point) -> Option<ts::Node> {
         self.root_node().and_then(|root| {
             root.descendant_for_point_range(point.clone().into(), point.clone().into())
@@ -377,7 +710,79 @@ impl Document {
         })
     }
 
+    /// What part of a component `point` is in. Broken code is often parsed as
+    /// `ERROR` nodes that no longer sit in the right section, so when the tree
+    /// is of no help the surrounding braces are used instead.
     pub fn context_at_point(&self, point: &Point) -> Context {
+        let from_tree = self.tree_context_at_point(point);
+        let broken = self.node_at_point(point).is_some_and(|n| {
+            n.is_error() || n.is_missing() || n.parent_until(|p| p.is_error()).is_some()
+        });
+        if broken
+            || (self.has_syntax_errors()
+                && matches!(from_tree, Context::Toplevel | Context::Component))
+        {
+            self.block_context_at_point(point)
+                .map_or(from_tree, |(context, _)| context)
+        } else {
+            from_tree
+        }
+    }
+
+    /// The name of the component that `point` is in
+    pub fn component_at_point(&self, point: &Point) -> Option<String> {
+        self.node_at_point(point)
+            .and_then(|n| self.enclosing_component_name(n))
+            .or_else(|| self.block_context_at_point(point)?.1)
+    }
+
+    /// The context of `point` found by matching up the braces before it and
+    /// looking at the keywords in front of the ones that are still open,
+    /// together with the name of the enclosing component.
+    fn block_context_at_point(&self, point: &Point) -> Option<(Context, Option<String>)> {
+        let offset = self.point_to_byte(point)?;
+        static COMP_RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"\bcomponent\s+(\w+)").unwrap());
+        let mut context = None;
+        let mut comp_name = None;
+        for header in open_blocks(&self.text[..offset]).iter().rev() {
+            let words: Vec<&str> = header
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|w| !w.is_empty())
+                .collect();
+            if let Some(caps) = COMP_RE.captures(header) {
+                comp_name = Some(caps[1].to_string());
+                context.get_or_insert(Context::Component);
+                break;
+            }
+            let block = if words.contains(&"group") {
+                Some(Context::Group)
+            } else {
+                match words.first() {
+                    Some(&"cells") => Some(Context::Cells),
+                    Some(&"wires") => Some(Context::Wires),
+                    Some(&"control") => Some(Context::Control),
+                    _ => None,
+                }
+            };
+            if context.is_none() {
+                context = block;
+            }
+        }
+        Some((context.unwrap_or(Context::Toplevel), comp_name))
+    }
+
+    /// The latency of the `static<N>` group that `point` is in
+    pub fn static_latency_at(&self, point: &Point) -> Option<u64> {
+        let offset = self.point_to_byte(point)?;
+        let header = open_blocks(&self.text[..offset])
+            .into_iter()
+            .rev()
+            .find(|header| header.contains("group"))?;
+        STATIC_GROUP.captures(&header)?[1].parse().ok()
+    }
+
+    fn tree_context_at_point(&self, point: &Point) -> Context {
         self.node_at_point(&point)
             .and_then(|n| {
                 if n.kind() == "component" {
@@ -408,6 +813,74 @@ impl Document {
             .unwrap_or(Context::Toplevel)
     }
 
+    pub fn point_to_byte(&self, point: &Point) -> Option<usize> {
+        let mut offset = 0;
+        for (row, line) in self.text.split('\n').enumerate() {
+            if row == point.row() {
+                return (point.column() <= line.len()).then_some(offset + point.column());
+            }
+            offset += line.len() + 1;
+        }
+        None
+    }
+
+    /// The text of the statement surrounding `point`, split in two at `point`.
+    /// Statements are delimited by `;` and braces.
+    pub fn statement_at(&self, point: &Point) -> Option<(&str, &str)> {
+        let offset = self.point_to_byte(point)?;
+        let delim = |c: char| c == ';' || c == '{' || c == '}';
+        let start = self.text[..offset].rfind(delim).map_or(0, |i| i + 1);
+        let end = self.text[offset..]
+            .find(delim)
+            .map_or(self.text.len(), |i| offset + i);
+        Some((&self.text[start..offset], &self.text[offset..end]))
+    }
+
+    /// The width of an expression like `r.out`, `32'd1`, `g[done]` or a port of
+    /// the component `comp`. `sigs` has to contain the signatures of every
+    /// component that `comp` instantiates.
+    pub fn expr_width(
+        &self,
+        comp: &str,
+        sigs: &HashMap<String, ComponentSig>,
+        expr: &str,
+    ) -> Option<u64> {
+        let expr = expr.trim();
+        static LITERAL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d+)'[dbxo]").unwrap());
+        static CELL_PORT: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^(\w+)\s*\.\s*(\w+)$").unwrap());
+        static HOLE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^\w+\s*\[\s*(go|done)\s*\]$").unwrap());
+        if let Some(caps) = LITERAL.captures(expr) {
+            caps[1].parse().ok()
+        } else if let Some(caps) = CELL_PORT.captures(expr) {
+            let ci = self.components.get(comp)?;
+            let cell_comp = ci.cells.get(&caps[1])?;
+            let args = ci.cell_args.get(&caps[1]).map_or(&[][..], |a| &a[..]);
+            sigs.get(cell_comp)?.port_width(&caps[2], args)
+        } else if HOLE.is_match(expr) {
+            Some(1)
+        } else {
+            sigs.get(comp)?.port_width(expr, &[])
+        }
+    }
+
+    /// The text on the line of `point` that comes before it
+    pub fn line_prefix(&self, point: &Point) -> Option<&str> {
+        self.text
+            .lines()
+            .nth(point.row())
+            .and_then(|line| line.get(..point.column()))
+    }
+
+    /// The text on the line of `point` that comes after it
+    pub fn line_suffix(&self, point: &Point) -> Option<&str> {
+        self.text
+            .lines()
+            .nth(point.row())
+            .and_then(|line| line.get(point.column()..))
+    }
+
     pub fn last_word_from_point(&self, point: &Point) -> Option<String> {
         let re = Regex::new(r"\b\w+\b").unwrap();
         self.text.lines().nth(point.row()).and_then(|cur_line| {
@@ -420,11 +893,226 @@ impl Document {
         })
     }
 
+    /// The cell a `port` node like `cell.port` refers to. Also returns the
+    /// group name of `hole` nodes.
+    pub fn port_cell(&self, port: &ts::Node) -> Option<&str> {
+        port.named_child(0)
+            .filter(|_| port.named_child_count() > 1)
+            .map(|cell| self.node_text(&cell))
+    }
+
+    /// The port name of a `port` node, which is the part after the `.` for
+    /// ports of cells
+    pub fn port_name(&self, port: &ts::Node) -> Option<&str> {
+        port.named_child(port.named_child_count().checked_sub(1)?)
+            .map(|name| self.node_text(&name))
+    }
+
+    /// The comments written directly above `node`, without comment markers
+    pub fn doc_comment(&self, node: &ts::Node) -> Option<String> {
+        let mut lines = vec![];
+        let mut cur = *node;
+        while let Some(prev) = cur.prev_sibling().filter(|p| {
+            p.kind() == "comment" && p.end_position().row + 1 >= cur.start_position().row
+        }) {
+            let text = self.node_text(&prev);
+            let text = text
+                .strip_prefix("/*")
+                .and_then(|t| t.strip_suffix("*/"))
+                .unwrap_or_else(|| text.trim_start_matches('/'));
+            lines.push(
+                text.lines()
+                    .map(|l| l.trim().trim_start_matches('*').trim())
+                    .join("\n")
+                    .trim()
+                    .to_string(),
+            );
+            cur = prev;
+        }
+        lines.reverse();
+        Some(lines.join("\n")).filter(|doc| !doc.is_empty())
+    }
+
+    /// The header of the component or primitive `name` defined in this file,
+    /// from its name up to the end of its signature
+    pub fn definition_header(&self, name: &str) -> Option<(String, ts::Node<'_>)> {
+        let def = self
+            .components()
+            .find(|n| self.node_text(n) == name)
+            .and_then(|n| n.parent_until_names(&["component", "primitive"]))?;
+        let mut cursor = def.walk();
+        let end = def
+            .named_children(&mut cursor)
+            .find(|c| c.kind() == "signature")
+            .map_or(def.end_byte(), |sig| sig.end_byte());
+        let header = self.text[def.start_byte()..end]
+            .lines()
+            .map(str::trim_end)
+            .join("\n");
+        Some((header, def))
+    }
+
+    /// The range of `node` extended to cover its whole lines when nothing
+    /// else is written on them. Useful for deleting a node without leaving
+    /// a blank line behind.
+    pub fn line_range(&self, node: &ts::Node) -> Range {
+        let before = &self.text[..node.start_byte()];
+        let after = &self.text[node.end_byte()..];
+        let line_before = before.rsplit('\n').next().unwrap_or("");
+        let line_after = after.split('\n').next().unwrap_or("");
+        if line_before.trim().is_empty() && line_after.trim().is_empty() {
+            Range::new(
+                Point::new(node.start_position().row, 0),
+                Point::new(node.end_position().row + 1, 0),
+            )
+        } else {
+            Range::from(*node)
+        }
+    }
+
+    /// The whitespace at the start of the line containing `node`
+    pub fn indentation(&self, node: &ts::Node) -> &str {
+        let line = self.text[..node.start_byte()]
+            .rsplit('\n')
+            .next()
+            .unwrap_or("");
+        &line[..line.len() - line.trim_start().len()]
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
     pub fn node_text(&self, node: &ts::Node) -> &str {
         node.utf8_text(self.text.as_bytes()).unwrap()
     }
 }
 
+/// The header of a `static<N> group`, with the latency `N`
+static STATIC_GROUP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bstatic\s*<\s*(\d+)\s*>\s*group\b").unwrap());
+
+/// The configured library paths, with `~` and relative paths resolved
+fn library_paths(config: &Config) -> impl Iterator<Item = PathBuf> + '_ {
+    config
+        .calyx_lsp
+        .library_paths
+        .iter()
+        .map(|p| PathBuf::from(p).resolve().into_owned())
+}
+
+/// Every `.futil` file in the library paths, with the string it would be
+/// imported as. This walks the file system, so `Config` caches the result.
+fn walk_library(config: &Config) -> Vec<(PathBuf, String)> {
+    // library paths often point at a whole Calyx checkout, so we only look
+    // at the top few levels where the primitives live
+    const MAX_DEPTH: usize = 2;
+
+    fn walk(dir: &Path, depth: usize, res: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            } else if path.is_dir() && depth > 1 {
+                walk(&path, depth - 1, res);
+            } else if path.extension().is_some_and(|ext| ext == "futil") {
+                res.push(path);
+            }
+        }
+    }
+
+    library_paths(config)
+        .flat_map(|lib_path| {
+            let mut files = vec![];
+            walk(&lib_path, MAX_DEPTH, &mut files);
+            files.into_iter().filter_map(move |file| {
+                file.strip_prefix(&lib_path)
+                    .ok()
+                    .map(|rel| (file.clone(), rel.to_string_lossy().to_string()))
+            })
+        })
+        .unique_by(|(_, import)| import.clone())
+        .collect()
+}
+
+/// `text` without any whitespace, to compare code regardless of formatting
+pub fn strip_ws(text: &str) -> String {
+    text.split_whitespace().collect()
+}
+
+/// The text in front of every `{` in `text` that is not closed yet, from the
+/// outermost to the innermost block. Comments and strings are skipped.
+fn open_blocks(text: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut header = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                chars.by_ref().find(|&c| {
+                    let end = prev == '*' && c == '/';
+                    prev = c;
+                    end
+                });
+            }
+            '"' => {
+                chars.by_ref().find(|&c| c == '"');
+            }
+            '{' => blocks.push(std::mem::take(&mut header)),
+            '}' => {
+                blocks.pop();
+                header.clear();
+            }
+            ';' => header.clear(),
+            _ => header.push(c),
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(text: &str) -> Vec<String> {
+        open_blocks(text).iter().map(|h| strip_ws(h)).collect()
+    }
+
+    #[test]
+    fn open_blocks_nested() {
+        let text = "component main() -> () {\n  wires {\n    group g {\n      r.in = 1'd1;\n";
+        assert_eq!(headers(text), ["componentmain()->()", "wires", "groupg"]);
+    }
+
+    #[test]
+    fn open_blocks_closed() {
+        let text = "component main() -> () {\n  cells { r = std_reg(1); }\n  wires {\n";
+        assert_eq!(headers(text), ["componentmain()->()", "wires"]);
+    }
+
+    #[test]
+    fn open_blocks_skip_comments() {
+        let text = "wires { // not a block {\n  /* nor { this */ group g {\n";
+        assert_eq!(headers(text), ["wires", "groupg"]);
+    }
+
+    #[test]
+    fn open_blocks_skip_strings() {
+        let text = "component main() -> () {\n  @attr(\"}\") group g {\n";
+        assert_eq!(headers(text), ["componentmain()->()", "@attr()groupg"]);
+    }
+}
+
 // Maybe useful functions for some point later
 // -------
 // fn apply_line_bytes_edit(&self, event: &lspt::TextDocumentContentChangeEvent) {
@@ -0,0 +1,749 @@
+//! Diagnostics computed from the syntax tree alone, without running the
+//! compiler. They are cheap enough to publish after every edit.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types as lspt;
+use tree_sitter as ts;
+
+use crate::{
+    convert::{Point, Range},
+    document::{strip_ws, Assignment, ComponentSig, Document},
+    ts_utils::{Descendants, ParentUntil},
+};
+
+/// A quick fix for a lint that deletes some code, stored in the `data` of the
+/// diagnostic so that the code action does not need to compute it again
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteFix {
+    pub title: String,
+    pub range: lspt::Range,
+}
+
+pub trait LintProvider {
+    /// A diagnostic for every `ERROR` and `MISSING` node in the tree
+    fn syntax_errors(&self) -> Vec<lspt::Diagnostic>;
+
+    /// Warnings about code that is valid but probably wrong. `imports` maps
+    /// every import of this file to the components it makes available, and
+    /// `sigs` has the signatures of every component visible from this file.
+    fn lints(
+        &self,
+        imports: &HashMap<String, HashSet<String>>,
+        sigs: &HashMap<String, ComponentSig>,
+    ) -> Vec<lspt::Diagnostic>;
+}
+
+impl LintProvider for Document {
+    fn syntax_errors(&self) -> Vec<lspt::Diagnostic> {
+        let mut errors = vec![];
+        if let Some(root) = self.root_node().filter(|root| root.has_error()) {
+            self.collect_syntax_errors(root, &mut errors);
+        }
+        errors
+    }
+
+    fn lints(
+        &self,
+        imports: &HashMap<String, HashSet<String>>,
+        sigs: &HashMap<String, ComponentSig>,
+    ) -> Vec<lspt::Diagnostic> {
+        let mut lints = self.width_lints(sigs);
+        lints.extend(self.direction_lints(sigs));
+        // references inside broken code are easily missed, and warnings about
+        // them would only add noise to the syntax errors
+        if self.has_syntax_errors() {
+            return lints;
+        }
+        lints.extend(self.unused_definitions());
+        lints.extend(self.unused_imports(imports));
+        lints.extend(self.done_lints(sigs));
+        lints.extend(self.driver_lints());
+        lints
+    }
+}
+
+impl Document {
+    /// Ports with more than one driver: several unguarded assignments in one
+    /// group, continuous assignments together with a group, or groups that
+    /// run in parallel
+    fn driver_lints(&self) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut lints = vec![];
+        for comp in self.captures(root, "(component) @comp")["comp"].iter() {
+            let groups: Vec<(String, Vec<Assignment>)> = self
+                .captures(*comp, "(group (ident) @group)")["group"]
+                .iter()
+                .filter_map(|name| {
+                    let def = name.parent()?;
+                    Some((self.node_text(name).to_string(), self.assignments(def)))
+                })
+                .collect();
+            let continuous: Vec<Assignment> = self.captures(*comp, "(wires) @wires")["wires"]
+                .iter()
+                .flat_map(|wires| self.assignments(*wires))
+                .filter(|a| a.node.parent_until(|p| p.kind() == "group").is_none())
+                .collect();
+
+            for (group, assigns) in &groups {
+                let unguarded = assigns.iter().filter(|a| a.guard_text(self).is_none());
+                for (dest, writes) in unguarded.into_group_map_by(|a| strip_ws(a.dest_text(self))) {
+                    if writes.len() > 1 {
+                        for write in &writes {
+                            lints.push(self.conflict(
+                                write,
+                                &writes,
+                                format!("`{dest}` is written more than once in group `{group}`"),
+                            ));
+                        }
+                    }
+                }
+                for write in assigns.iter().filter(|a| !a.dest_text(self).contains('[')) {
+                    let dest = strip_ws(write.dest_text(self));
+                    let others: Vec<&Assignment> = continuous
+                        .iter()
+                        .filter(|c| strip_ws(c.dest_text(self)) == dest)
+                        .collect();
+                    if !others.is_empty() {
+                        let mut all = others.clone();
+                        all.push(write);
+                        lints.push(self.conflict(
+                            write,
+                            &all,
+                            format!("`{dest}` is written both in group `{group}` and in `wires`"),
+                        ));
+                    }
+                }
+            }
+
+            lints.extend(self.par_conflicts(*comp, &groups));
+        }
+        lints
+    }
+
+    /// Groups that run at the same time in a `par` block and write the same
+    /// cell port. The warning is shown on the enable of the first group.
+    fn par_conflicts(
+        &self,
+        comp: ts::Node,
+        groups: &[(String, Vec<Assignment>)],
+    ) -> Vec<lspt::Diagnostic> {
+        let cell_writes = |group: &str| -> Vec<&Assignment> {
+            groups
+                .iter()
+                .filter(|(name, _)| name == group)
+                .flat_map(|(_, assigns)| assigns)
+                .filter(|a| a.dest_port().is_some_and(|p| self.port_cell(&p).is_some()))
+                .collect()
+        };
+        let mut lints = vec![];
+        // the `par` token is either next to the braces, or on a parent that
+        // wraps the block
+        let blocks = comp.descendants().into_iter().filter(|n| {
+            n.has_child_token("{")
+                && (n.has_child_token("par")
+                    || (!n.has_child_token("seq")
+                        && n.parent()
+                            .is_some_and(|p| p.has_child_token("par") && !p.has_child_token("{"))))
+        });
+        for block in blocks {
+            // the groups enabled by each statement of the block
+            let mut cursor = block.walk();
+            let branches: Vec<Vec<ts::Node>> = block
+                .named_children(&mut cursor)
+                .map(|stmt| {
+                    std::iter::once(stmt)
+                        .chain(stmt.descendants())
+                        .filter(|n| n.kind() == "enable")
+                        .collect()
+                })
+                .collect();
+            for (i, left) in branches.iter().enumerate() {
+                for right in &branches[i + 1..] {
+                    for (a, b) in left.iter().cartesian_product(right) {
+                        let (ga, gb) = (self.enable_name(a), self.enable_name(b));
+                        let (wa, wb) = (cell_writes(ga), cell_writes(gb));
+                        let shared: Vec<String> = wa
+                            .iter()
+                            .map(|w| strip_ws(w.dest_text(self)))
+                            .filter(|d| wb.iter().any(|w| strip_ws(w.dest_text(self)) == *d))
+                            .unique()
+                            .collect();
+                        if shared.is_empty() {
+                            continue;
+                        }
+                        let writes: Vec<&Assignment> = wa
+                            .into_iter()
+                            .chain(wb)
+                            .filter(|w| shared.contains(&strip_ws(w.dest_text(self))))
+                            .collect();
+                        let ports = shared.iter().map(|d| format!("`{d}`")).join(", ");
+                        lints.push(lspt::Diagnostic {
+                            related_information: Some(self.related(&writes)),
+                            ..lint(
+                                Range::from(*a),
+                                lspt::DiagnosticSeverity::WARNING,
+                                format!("`{ga}` and `{gb}` run in parallel and both write {ports}"),
+                            )
+                        });
+                    }
+                }
+            }
+        }
+        lints
+    }
+
+    fn enable_name(&self, enable: &ts::Node) -> &str {
+        enable
+            .named_child(0)
+            .map_or("", |name| self.node_text(&name))
+    }
+
+    /// A warning on `write` that points to the other assignments in `writes`
+    fn conflict(
+        &self,
+        write: &Assignment,
+        writes: &[&Assignment],
+        message: String,
+    ) -> lspt::Diagnostic {
+        let others: Vec<&Assignment> = writes
+            .iter()
+            .copied()
+            .filter(|w| w.node != write.node)
+            .collect();
+        lspt::Diagnostic {
+            related_information: Some(self.related(&others)),
+            ..lint(
+                Range::from(write.node),
+                lspt::DiagnosticSeverity::WARNING,
+                message,
+            )
+        }
+    }
+
+    fn related(&self, writes: &[&Assignment]) -> Vec<lspt::DiagnosticRelatedInformation> {
+        writes
+            .iter()
+            .map(|w| lspt::DiagnosticRelatedInformation {
+                location: lspt::Location::new(self.url.clone(), Range::from(w.node).into()),
+                message: format!("`{}` is also written here", strip_ws(w.dest_text(self))),
+            })
+            .collect()
+    }
+
+    /// Assignments whose sides have different widths, and guards that are not
+    /// 1 bit wide. Assignments that do not parse are skipped, so this also
+    /// works while the rest of the file is broken.
+    fn width_lints(&self, sigs: &HashMap<String, ComponentSig>) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut lints = vec![];
+        for name in self.captures(root, "(component (ident) @name)")["name"].iter() {
+            let comp = self.node_text(name);
+            let Some(def) = name.parent() else {
+                continue;
+            };
+            for assign in self.complete_assignments(def) {
+                let dest = assign.dest_text(self);
+                let src = assign.src_text(self);
+                let widths = (
+                    self.expr_width(comp, sigs, dest),
+                    self.expr_width(comp, sigs, src),
+                );
+                if let (Some(dw), Some(sw)) = widths {
+                    if dw != sw {
+                        lints.push(lint(
+                            Range::from(assign.node),
+                            lspt::DiagnosticSeverity::ERROR,
+                            format!("width mismatch: `{dest}` ({dw}) = `{src}` ({sw})"),
+                        ));
+                    }
+                }
+                if let Some(guard) = assign.guard_text(self) {
+                    for (atom, message) in self.guard_errors(comp, sigs, guard) {
+                        lints.push(lint(
+                            self.text_range(&assign.node, atom),
+                            lspt::DiagnosticSeverity::ERROR,
+                            message,
+                        ));
+                    }
+                }
+            }
+        }
+        lints
+    }
+
+    /// Assignments that write an output port of a cell or an input port of
+    /// the component itself, or read an input port of a cell
+    fn direction_lints(&self, sigs: &HashMap<String, ComponentSig>) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut lints = vec![];
+        for name in self.captures(root, "(component (ident) @name)")["name"].iter() {
+            let comp = self.node_text(name);
+            let (Some(def), Some(info)) = (name.parent(), self.components.get(comp)) else {
+                continue;
+            };
+            let cell_sig = |port: &ts::Node| {
+                let cell = self.port_cell(port)?;
+                sigs.get(info.cells.get(cell)?).map(|sig| (cell, sig))
+            };
+            for assign in self.complete_assignments(def) {
+                let Some(dest) = assign.dest_port().filter(|p| p.kind() == "port") else {
+                    continue;
+                };
+                let Some(port) = self.port_name(&dest) else {
+                    continue;
+                };
+                let message = match cell_sig(&dest) {
+                    Some((cell, sig)) if sig.outputs.iter().any(|o| o == port) => Some(format!(
+                        "cannot write `{cell}.{port}`, it is an output port"
+                    )),
+                    None if self.port_cell(&dest).is_none()
+                        && sigs
+                            .get(comp)
+                            .is_some_and(|sig| sig.inputs.iter().any(|i| i == port)) =>
+                    {
+                        Some(format!(
+                            "cannot write `{port}`, it is an input port of `{comp}`"
+                        ))
+                    }
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    lints.push(lint(
+                        Range::from(dest),
+                        lspt::DiagnosticSeverity::ERROR,
+                        message,
+                    ));
+                }
+                for read in assign.reads().into_iter().filter(|r| r.kind() == "port") {
+                    let (Some((cell, sig)), Some(port)) = (cell_sig(&read), self.port_name(&read))
+                    else {
+                        continue;
+                    };
+                    if sig.inputs.iter().any(|i| i == port) {
+                        lints.push(lint(
+                            Range::from(read),
+                            lspt::DiagnosticSeverity::ERROR,
+                            format!("cannot read `{cell}.{port}`, it is an input port"),
+                        ));
+                    }
+                }
+            }
+        }
+        lints
+    }
+
+    /// The assignments in `def` that parsed without errors
+    fn complete_assignments<'a>(&'a self, def: ts::Node<'a>) -> Vec<Assignment<'a>> {
+        self.assignments(def)
+            .into_iter()
+            .filter(|a| !a.node.has_error() && a.node.parent_until(|p| p.is_error()).is_none())
+            .collect()
+    }
+
+    /// The parts of `guard` with the wrong width. Operands of `!`, `&` and `|`
+    /// have to be 1 bit wide, and the two sides of a comparison have to be
+    /// equally wide.
+    fn guard_errors<'g>(
+        &self,
+        comp: &str,
+        sigs: &HashMap<String, ComponentSig>,
+        guard: &'g str,
+    ) -> Vec<(&'g str, String)> {
+        static CMP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"==|!=|<=|>=|<|>").unwrap());
+        guard_atoms(guard)
+            .into_iter()
+            .map(str::trim)
+            // timing guards of static groups are not ports
+            .filter(|atom| !atom.is_empty() && !atom.starts_with('%'))
+            .filter_map(|atom| match CMP_RE.find(atom) {
+                Some(op) => {
+                    let (left, right) = (atom[..op.start()].trim(), atom[op.end()..].trim());
+                    let lw = self.expr_width(comp, sigs, left)?;
+                    let rw = self.expr_width(comp, sigs, right)?;
+                    (lw != rw).then(|| {
+                        (
+                            atom,
+                            format!(
+                                "width mismatch: `{left}` ({lw}) compared with `{right}` ({rw})"
+                            ),
+                        )
+                    })
+                }
+                None => {
+                    let width = self.expr_width(comp, sigs, atom)?;
+                    (width != 1).then(|| {
+                        (
+                            atom,
+                            format!("guard `{atom}` is {width} bits wide instead of 1"),
+                        )
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// The range of the first occurrence of `needle` inside of `node`
+    fn text_range(&self, node: &ts::Node, needle: &str) -> Range {
+        self.node_text(node)
+            .find(needle)
+            .and_then(|offset| {
+                let start = self.byte_to_point(node.start_byte() + offset)?;
+                let end = self.byte_to_point(node.start_byte() + offset + needle.len())?;
+                Some(Range::new(start, end))
+            })
+            .unwrap_or_else(|| Range::from(*node))
+    }
+
+    /// Groups that never assign their `done` hole, and groups whose `done`
+    /// waits for a register that is never written
+    fn done_lints(&self, sigs: &HashMap<String, ComponentSig>) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        static STATIC_RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^\s*static\s*<").unwrap());
+        let mut lints = vec![];
+        for name in self.captures(root, "(group (ident) @group)")["group"].iter() {
+            let def = name.parent().unwrap();
+            // comb groups have no `done`, and static groups finish on their own
+            if def.has_child_token("comb")
+                || def.has_child_token("static")
+                || STATIC_RE.is_match(self.node_text(&def))
+            {
+                continue;
+            }
+            let group = self.node_text(name);
+            let assigns = self.assignments(def);
+            let done = assigns
+                .iter()
+                .find(|a| strip_ws(a.dest_text(self)) == format!("{group}[done]"));
+            let Some(done) = done else {
+                lints.push(lint(
+                    Range::from(*name),
+                    lspt::DiagnosticSeverity::WARNING,
+                    format!("group `{group}` never assigns `{group}[done]`"),
+                ));
+                continue;
+            };
+
+            let cells = self
+                .enclosing_component_name(def)
+                .and_then(|comp| self.components.get(&comp));
+            for port in done.reads().iter().filter(|p| p.kind() == "port") {
+                let (Some(cell), Some("done")) = (self.port_cell(port), self.port_name(port))
+                else {
+                    continue;
+                };
+                let has_write_en = cells
+                    .and_then(|ci| ci.cells.get(cell))
+                    .and_then(|comp| sigs.get(comp))
+                    .is_some_and(|sig| sig.inputs.iter().any(|p| p == "write_en"));
+                let asserted = assigns.iter().any(|a| {
+                    strip_ws(a.dest_text(self)) == format!("{cell}.write_en")
+                        && !is_zero(a.src_text(self))
+                });
+                if has_write_en && !asserted {
+                    lints.push(lint(
+                        Range::from(done.node),
+                        lspt::DiagnosticSeverity::WARNING,
+                        format!(
+                            "`{group}[done]` waits for `{cell}.done`, \
+                             but `{cell}.write_en` is never asserted in `{group}`"
+                        ),
+                    ));
+                }
+            }
+        }
+        lints
+    }
+
+    /// Cells that are never used, and groups that are never enabled or
+    /// otherwise referred to, in every component
+    fn unused_definitions(&self) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut lints = vec![];
+        for comp in self.captures(root, "(component) @comp")["comp"].iter() {
+            let cells = self.captures(*comp, "(cell_assignment (ident) @cell)")["cell"].clone();
+            for name in cells {
+                let def = name.parent().unwrap();
+                // ref cells are passed in by the parent and external cells are
+                // used from outside of Calyx, so they count as used
+                if def.has_child_token("ref") || self.is_external(&name) {
+                    continue;
+                }
+                if self.ident_uses(*comp, &name).next().is_none() {
+                    lints.push(self.unused(def, "cell", self.node_text(&name)));
+                }
+            }
+            let groups = self.captures(*comp, "(group (ident) @group)")["group"].clone();
+            for name in groups {
+                let def = name.parent().unwrap();
+                // a group refers to its own `done` hole
+                let outside = self
+                    .ident_uses(*comp, &name)
+                    .any(|n| n.parent_until(|p| *p == def).is_none());
+                if !outside {
+                    lints.push(self.unused(def, "group", self.node_text(&name)));
+                }
+            }
+        }
+        lints
+    }
+
+    /// Imports that none of the cells in this file instantiate a component of
+    fn unused_imports(&self, imports: &HashMap<String, HashSet<String>>) -> Vec<lspt::Diagnostic> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let instantiated: HashSet<&str> = self
+            .components
+            .values()
+            .flat_map(|ci| ci.cells.values().map(String::as_str))
+            .collect();
+        self.captures(root, "(import (string) @file)")["file"]
+            .iter()
+            .filter_map(|file| {
+                let import = self.node_text(file).replace('"', "");
+                let available = imports.get(&import).filter(|comps| !comps.is_empty())?;
+                if available.iter().any(|c| instantiated.contains(c.as_str())) {
+                    None
+                } else {
+                    Some(self.unused(file.parent()?, "import", &import))
+                }
+            })
+            .collect()
+    }
+
+    /// Every `ident` in `scope` with the same text as the definition `name`,
+    /// other than `name` itself. Port names after a `.` are not references.
+    fn ident_uses<'a>(
+        &'a self,
+        scope: ts::Node<'a>,
+        name: &ts::Node<'a>,
+    ) -> impl Iterator<Item = ts::Node<'a>> + 'a {
+        let name = *name;
+        let text = self.node_text(&name);
+        scope.descendants().into_iter().filter(move |n| {
+            n.kind() == "ident"
+                && *n != name
+                && self.node_text(n) == text
+                && !n.parent().is_some_and(|p| {
+                    p.kind() == "port"
+                        && p.named_child_count() > 1
+                        && p.named_child(p.named_child_count() - 1) == Some(*n)
+                })
+        })
+    }
+
+    /// Whether the cell called `name` has the `external` attribute, written
+    /// as `@external` in front of the name or as `<"external"=1>` after it
+    fn is_external(&self, name: &ts::Node) -> bool {
+        let text = self.text();
+        let start = text[..name.start_byte()]
+            .rfind([';', '{'])
+            .map_or(0, |i| i + 1);
+        let end = text[name.end_byte()..]
+            .find('=')
+            .map_or(text.len(), |i| name.end_byte() + i);
+        let attrs = &text[start..end];
+        attrs.contains("@external") || attrs.contains("\"external\"")
+    }
+
+    /// A warning that the definition `def` of the `what` called `name` is
+    /// unused, with a fix that deletes it
+    fn unused(&self, def: ts::Node, what: &str, name: &str) -> lspt::Diagnostic {
+        let fix = DeleteFix {
+            title: format!("Remove unused {what} `{name}`"),
+            range: self.line_range(&def).into(),
+        };
+        lspt::Diagnostic {
+            tags: Some(vec![lspt::DiagnosticTag::UNNECESSARY]),
+            data: serde_json::to_value(fix).ok(),
+            ..lint(
+                Range::from(def),
+                lspt::DiagnosticSeverity::WARNING,
+                format!("{what} `{name}` is never used"),
+            )
+        }
+    }
+
+    fn collect_syntax_errors(&self, node: ts::Node, errors: &mut Vec<lspt::Diagnostic>) {
+        if node.is_missing() {
+            errors.push(lint(
+                Range::from(node),
+                lspt::DiagnosticSeverity::ERROR,
+                self.missing_message(&node),
+            ));
+        } else if node.is_error() {
+            // nested errors are part of this one
+            let (range, message) = self.error_message(&node);
+            errors.push(lint(range, lspt::DiagnosticSeverity::ERROR, message));
+        } else if node.has_error() {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                self.collect_syntax_errors(child, errors);
+            }
+        }
+    }
+
+    fn missing_message(&self, node: &ts::Node) -> String {
+        let expected = if node.is_named() {
+            describe(node.kind()).to_string()
+        } else {
+            format!("`{}`", node.kind())
+        };
+        match node.prev_sibling() {
+            Some(prev) => format!("expected {expected} after {}", self.describe_node(&prev)),
+            None => format!("expected {expected}"),
+        }
+    }
+
+    /// The message for an `ERROR` node, and the range to show it at. Text
+    /// that starts on a new line after an unfinished statement most likely
+    /// means the `;` was forgotten, which is reported at the end of that
+    /// statement instead.
+    fn error_message(&self, node: &ts::Node) -> (Range, String) {
+        let unfinished = node.prev_sibling().filter(|prev| {
+            prev.end_position().row < node.start_position().row
+                && !self.node_text(prev).trim_end().ends_with([';', '}'])
+                && (prev.kind() == "cell_assignment" || prev.has_child_token("="))
+        });
+        if let Some(prev) = unfinished {
+            let end = Point::from(prev.end_position());
+            return (
+                Range::new(end.clone(), end),
+                format!("expected `;` after {}", self.describe_node(&prev)),
+            );
+        }
+
+        let first = node
+            .descendants()
+            .into_iter()
+            .find(|n| n.child_count() == 0)
+            .unwrap_or(*node);
+        let context = node
+            .parent()
+            .filter(|p| p.kind() != "source_file")
+            .map(|p| format!(" in {}", describe(p.kind())))
+            .unwrap_or_default();
+        let token = self.node_text(&first);
+        let message = if token.is_empty() {
+            format!("syntax error{context}")
+        } else {
+            format!("unexpected `{token}`{context}")
+        };
+        (Range::from(first), message)
+    }
+
+    /// A name for what `node` is, for use in messages
+    fn describe_node(&self, node: &ts::Node) -> &'static str {
+        if node.kind() != "cell_assignment" && node.has_child_token("=") {
+            "assignment"
+        } else {
+            describe(node.kind())
+        }
+    }
+}
+
+/// `guard` split at its logical operators and parentheses, leaving ports,
+/// comparisons and timing guards
+fn guard_atoms(guard: &str) -> Vec<&str> {
+    let bytes = guard.as_bytes();
+    let mut atoms = vec![];
+    let mut start = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        let is_not = b == b'!' && bytes.get(i + 1) != Some(&b'=');
+        if is_not || b"&|()".contains(&b) {
+            atoms.push(&guard[start..i]);
+            start = i + 1;
+        }
+    }
+    atoms.push(&guard[start..]);
+    atoms
+}
+
+/// Whether `text` is a constant zero like `0` or `1'd0`
+fn is_zero(text: &str) -> bool {
+    let digits = text
+        .split_once('\'')
+        .map_or(text, |(_, value)| &value[1.min(value.len())..]);
+    !digits.is_empty() && digits.chars().all(|c| c == '0')
+}
+
+/// A readable name for a kind of node
+fn describe(kind: &str) -> &'static str {
+    match kind {
+        "ident" => "a name",
+        "import" => "import",
+        "string" => "a string",
+        "component" => "component",
+        "primitive" => "primitive",
+        "signature" => "signature",
+        "io_port_list" | "io_port" => "port list",
+        "cells" => "cells section",
+        "cell_assignment" => "cell",
+        "instantiation" => "instantiation",
+        "wires" | "wires_inner" => "wires section",
+        "group" => "group",
+        "control" => "control section",
+        "enable" => "group enable",
+        "port" | "hole" => "port",
+        _ => "statement",
+    }
+}
+
+/// A diagnostic from this module
+fn lint(range: Range, severity: lspt::DiagnosticSeverity, message: String) -> lspt::Diagnostic {
+    lspt::Diagnostic {
+        range: range.into(),
+        severity: Some(severity),
+        source: Some("calyx-lsp".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_zero_literals() {
+        assert!(is_zero("0"));
+        assert!(is_zero("1'b0"));
+        assert!(is_zero("32'd0"));
+        assert!(is_zero("4'h00"));
+        assert!(!is_zero("1'd1"));
+        assert!(!is_zero("8'd10"));
+        assert!(!is_zero("1'b"));
+        assert!(!is_zero("r.out"));
+    }
+
+    #[test]
+    fn guard_atoms_split_on_not_but_not_on_neq() {
+        let atoms: Vec<_> = guard_atoms("!a.out & (b.out != 1'd0 | c.done)")
+            .into_iter()
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .collect();
+        assert_eq!(atoms, ["a.out", "b.out != 1'd0", "c.done"]);
+    }
+
+    #[test]
+    fn guard_atoms_single() {
+        assert_eq!(guard_atoms("r.out == 2'd3"), ["r.out == 2'd3"]);
+    }
+}
@@ -1,29 +1,37 @@
+mod attributes;
+mod code_action;
 mod completion;
 mod convert;
 mod diagnostic;
 mod document;
 mod goto_definition;
+mod lint;
 mod log;
 mod query_result;
+mod signature_help;
 mod ts_utils;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
 
 use convert::{Point, Range};
-use diagnostic::Diagnostic;
+use diagnostic::{CalyxError, Diagnostic};
 use document::{ComponentSig, Document};
 use goto_definition::DefinitionProvider;
+use lint::LintProvider;
 use query_result::QueryResult2;
 use serde::Deserialize;
 use tower_lsp::lsp_types as lspt;
 use tower_lsp::{jsonrpc, Client, LanguageServer, LspService, Server};
 use tree_sitter as ts;
 
-use crate::completion::CompletionProvider;
+use crate::code_action::CodeActionProvider;
+use crate::completion::{CompletionProvider, CompletionRes, ResolveData};
 use crate::log::Debug;
+use crate::signature_help::SignatureHelpProvider;
 
 extern "C" {
     fn tree_sitter_calyx() -> ts::Language;
@@ -33,6 +41,9 @@ extern "C" {
 struct Config {
     #[serde(rename = "calyx-lsp")]
     calyx_lsp: CalyxLspConfig,
+    /// The `.futil` files in the library paths, see `Document::library_files`
+    #[serde(skip)]
+    library_files: OnceLock<Vec<(PathBuf, String)>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,21 +60,53 @@ impl Default for CalyxLspConfig {
     }
 }
 
+/// Diagnostics by the file they are in
+type FileDiagnostics = HashMap<lspt::Url, Vec<lspt::Diagnostic>>;
+
+/// The state of the server. Clones share it, so that work can continue in a
+/// spawned task after a handler has returned.
+#[derive(Clone)]
 struct Backend {
     client: Client,
-    open_docs: RwLock<HashMap<lspt::Url, document::Document>>,
-    config: RwLock<Config>,
+    open_docs: Arc<RwLock<HashMap<lspt::Url, document::Document>>>,
+    config: Arc<RwLock<Config>>,
     /// A map from each open file, to the components defined in that file
-    symbols: RwLock<HashMap<lspt::Url, HashMap<String, ComponentSig>>>,
+    symbols: Arc<RwLock<HashMap<lspt::Url, HashMap<String, ComponentSig>>>>,
+    /// How many times each open file has been edited, to tell whether
+    /// diagnostics that are still being computed are out of date
+    edits: Arc<RwLock<HashMap<lspt::Url, u64>>>,
+    /// The errors the compiler reported the last time each file was checked,
+    /// by the file they are in. Checking a file also finds errors in the
+    /// files it imports, which are kept apart from their own checks.
+    compiler_diagnostics: Arc<RwLock<HashMap<lspt::Url, FileDiagnostics>>>,
+    /// What the imports of each file make available, see `import_env`
+    import_envs: Arc<RwLock<HashMap<lspt::Url, ImportEnv>>>,
+}
+
+/// The components that the imports of a file make available
+#[derive(Clone, Default)]
+struct ImportEnv {
+    /// The imports as they are written in the file
+    imports: Vec<String>,
+    /// The components that each import makes available
+    components: HashMap<String, HashSet<String>>,
+    /// The signatures of every imported component
+    sigs: HashMap<String, ComponentSig>,
 }
 
+/// How long to wait after the last edit before checking a file
+const DIAGNOSTICS_DELAY: Duration = Duration::from_millis(300);
+
 impl Backend {
     fn new(client: Client) -> Self {
         Self {
             client,
-            open_docs: RwLock::new(HashMap::default()),
-            config: RwLock::new(Config::default()),
-            symbols: RwLock::new(HashMap::default()),
+            open_docs: Arc::default(),
+            config: Arc::default(),
+            symbols: Arc::default(),
+            edits: Arc::default(),
+            compiler_diagnostics: Arc::default(),
+            import_envs: Arc::default(),
         }
     }
 
@@ -104,6 +147,19 @@ impl Backend {
         self.read_document(&uri, reader)
     }
 
+    /// Read the document at `uri` if it is open, otherwise parse the file
+    /// without opening it
+    fn read_file<F, T>(&self, uri: &lspt::Url, mut reader: F) -> Option<T>
+    where
+        F: FnMut(&Document) -> Option<T>,
+    {
+        if self.exists(uri) {
+            return self.read_document(uri, reader);
+        }
+        let text = fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+        reader(&Document::new_with_text(uri.clone(), &text))
+    }
+
     fn update<F>(&self, uri: &lspt::Url, updater: F)
     where
         F: FnMut(&mut Document) -> (),
@@ -131,41 +187,355 @@ impl Backend {
             });
     }
 
+    /// The signatures of every component visible from `url`: the ones defined
+    /// in the file itself and the ones from its (transitive) imports.
+    fn signature_env(&self, url: &lspt::Url, config: &Config) -> HashMap<String, ComponentSig> {
+        let mut env: HashMap<_, _> = self
+            .read_and_open(url, |doc| Some(doc.signatures().collect()))
+            .unwrap_or_default();
+        for (name, sig) in self.imported_signatures(url, config) {
+            env.entry(name).or_insert(sig);
+        }
+        env
+    }
+
+    /// The signatures of the components that `url` imports, directly or not
+    fn imported_signatures(
+        &self,
+        url: &lspt::Url,
+        config: &Config,
+    ) -> HashMap<String, ComponentSig> {
+        let mut env = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut todo = vec![url.clone()];
+        while let Some(next) = todo.pop() {
+            if !seen.insert(next.clone()) {
+                continue;
+            }
+            if let Some((sigs, imports)) = self.read_and_open(&next, |doc| {
+                Some((
+                    doc.signatures().collect::<Vec<_>>(),
+                    doc.resolved_imports(config).collect::<Vec<_>>(),
+                ))
+            }) {
+                for (name, sig) in sigs.into_iter().filter(|_| next != *url) {
+                    env.entry(name).or_insert(sig);
+                }
+                todo.extend(
+                    imports
+                        .into_iter()
+                        .filter_map(|path| lspt::Url::from_file_path(path).ok()),
+                );
+            }
+        }
+        env
+    }
+
+    fn edit_count(&self, url: &lspt::Url) -> u64 {
+        self.edits.read().unwrap().get(url).copied().unwrap_or(0)
+    }
+
+    /// Check the current text of `url` with the compiler and publish the errors. Errors that are
+    /// fixed disappear because the whole set is replaced every time.
     async fn publish_diagnostics(&self, url: &lspt::Url) {
-        let lib_path: PathBuf = self.config.read().unwrap().calyx_lsp.library_paths[0]
-            .to_string()
-            .into();
-        let diags = self
-            .read_document(url, |doc| {
+        // without the compiler, only syntax errors and lints are reported
+        if !cfg!(feature = "diagnostics") {
+            return;
+        }
+        // the compiler can only check files on disk
+        let Ok(path) = url.to_file_path() else {
+            return;
+        };
+        let Some((text, bodies, search_paths)) = self.read_document(url, |doc| {
+            let config = self.config.read().unwrap();
+            Some((
+                doc.text().to_string(),
+                doc.component_bodies(),
+                doc.search_paths(&config),
+            ))
+        }) else {
+            return;
+        };
+        let edit = self.edit_count(url);
+        let checked = path.clone();
+        let Ok(errors) = tokio::task::spawn_blocking(move || {
+            Diagnostic::check_text(&checked, &text, &bodies, &search_paths)
+        })
+        .await
+        else {
+            return;
+        };
+        if self.edit_count(url) != edit {
+            // the text changed while checking, the next check will publish
+            return;
+        }
+        // errors in imported files belong to those files, and are pointed to
+        // from the import that brings them in
+        let mut by_url: HashMap<lspt::Url, Vec<CalyxError>> = HashMap::new();
+        for err in errors {
+            let err_url = if Path::new(&err.file_name) == path {
+                Some(url.clone())
+            } else {
+                file_url(Path::new(&err.file_name))
+            };
+            by_url
+                .entry(err_url.unwrap_or(url.clone()))
+                .or_default()
+                .push(err);
+        }
+        let mut published: Vec<(lspt::Url, Vec<lspt::Diagnostic>)> = by_url
+            .iter()
+            .map(|(err_url, errs)| {
+                let diags = self
+                    .read_and_open(err_url, |doc| Some(error_diagnostics(doc, errs)))
+                    .unwrap_or_default();
+                (err_url.clone(), diags)
+            })
+            .collect();
+        let mut import_diags: Vec<lspt::Diagnostic> = {
+            let config = self.config.read().unwrap();
+            published
+                .iter()
+                .filter(|(err_url, diags)| err_url != url && !diags.is_empty())
+                .filter_map(|(err_url, diags)| {
+                    let (import, dir) = self.import_reaching(url, err_url, &config)?;
+                    let range = self.read_document(url, |doc| doc.import_range(&import))?;
+                    Some(lspt::Diagnostic {
+                        range: range.into(),
+                        severity: Some(lspt::DiagnosticSeverity::ERROR),
+                        source: Some("calyx".to_string()),
+                        message: format!(
+                            "errors in imported file `{import}`, found in `{}`",
+                            dir.display()
+                        ),
+                        related_information: Some(
+                            diags
+                                .iter()
+                                .map(|diag| lspt::DiagnosticRelatedInformation {
+                                    location: lspt::Location::new(err_url.clone(), diag.range),
+                                    message: diag.message.clone(),
+                                })
+                                .collect(),
+                        ),
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        };
+        // say which library path an import was found in, since that depends on
+        // the order of the library paths
+        let found_in: Vec<lspt::Diagnostic> = {
+            let config = self.config.read().unwrap();
+            self.read_document(url, |doc| {
                 Some(
-                    Diagnostic::did_save(&url.to_file_path().unwrap(), &lib_path)
+                    doc.raw_imports()
                         .into_iter()
-                        .filter_map(|diag| {
-                            doc.byte_to_point(diag.pos_start).and_then(|s| {
-                                doc.byte_to_point(diag.pos_end)
-                                    .map(|e| (Range::new(s, e), diag.msg))
+                        .filter_map(|import| {
+                            let (dir, _) = doc
+                                .resolve_import(&import, &config)
+                                .filter(|(dir, _)| Some(dir.as_path()) != path.parent())?;
+                            Some(lspt::Diagnostic {
+                                range: doc.import_range(&import)?.into(),
+                                severity: Some(lspt::DiagnosticSeverity::HINT),
+                                source: Some("calyx".to_string()),
+                                message: format!("`{import}` found in `{}`", dir.display()),
+                                ..Default::default()
                             })
                         })
-                        .map(|(range, message)| lspt::Diagnostic {
-                            range: range.into(),
-                            severity: Some(lspt::DiagnosticSeverity::ERROR),
-                            code: None,
-                            code_description: None,
-                            source: Some("calyx".to_string()),
-                            message,
-                            related_information: None,
-                            tags: None,
-                            data: None,
-                        })
-                        .inspect(|diag| log::stdout!("{diag:#?}"))
                         .collect(),
                 )
             })
-            .unwrap_or(vec![]);
+            .unwrap_or_default()
+        };
+        import_diags.extend(found_in);
+        match published.iter_mut().find(|(u, _)| u == url) {
+            Some((_, diags)) => diags.extend(import_diags),
+            None => published.push((url.clone(), import_diags)),
+        }
+
+        // files that had errors from this check last time, but not anymore,
+        // are published again so that those errors disappear
+        let mut targets: HashSet<lspt::Url> = published.iter().map(|(u, _)| u.clone()).collect();
+        let stale = self
+            .compiler_diagnostics
+            .write()
+            .unwrap()
+            .insert(url.clone(), published.into_iter().collect())
+            .unwrap_or_default();
+        targets.extend(stale.into_keys());
+        for target in targets {
+            self.publish(&target).await;
+        }
+    }
+
+    /// Send every diagnostic of `url`: the syntax errors and lints of its
+    /// current tree, and the errors the compiler found in it from every file
+    /// that was checked
+    async fn publish(&self, url: &lspt::Url) {
+        let env = self.import_env(url);
+        let mut diags = self
+            .read_document(url, |doc| {
+                let mut sigs: HashMap<_, _> = doc.signatures().collect();
+                for (name, sig) in &env.sigs {
+                    sigs.entry(name.clone()).or_insert_with(|| sig.clone());
+                }
+                let mut diags = doc.syntax_errors();
+                diags.extend(doc.lints(&env.components, &sigs));
+                Some(diags)
+            })
+            .unwrap_or_default();
+        // the same error is often found by the checks of several files
+        let compiler: Vec<lspt::Diagnostic> = self
+            .compiler_diagnostics
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|by_file| by_file.get(url))
+            .flatten()
+            .cloned()
+            .collect();
+        for diag in compiler {
+            if !diags.contains(&diag) {
+                diags.push(diag);
+            }
+        }
         self.client
             .publish_diagnostics(url.clone(), diags, None)
             .await;
     }
+
+    /// What the imports of `url` make available. This is computed once and
+    /// kept until the imports of `url` change or the cache is cleared, since
+    /// finding the imported files needs to look at the disk.
+    fn import_env(&self, url: &lspt::Url) -> ImportEnv {
+        let imports = self
+            .read_document(url, |doc| Some(doc.raw_imports()))
+            .unwrap_or_default();
+        if let Some(env) = self.import_envs.read().unwrap().get(url) {
+            if env.imports == imports {
+                return env.clone();
+            }
+        }
+        let env = {
+            let config = self.config.read().unwrap();
+            ImportEnv {
+                imports,
+                components: self.import_components(url, &config),
+                sigs: self.imported_signatures(url, &config),
+            }
+        };
+        self.import_envs
+            .write()
+            .unwrap()
+            .insert(url.clone(), env.clone());
+        env
+    }
+
+    /// The imports of `url` that resolve, with the search path each was found
+    /// in and the file it refers to
+    fn direct_imports(&self, url: &lspt::Url, config: &Config) -> Vec<(String, PathBuf, PathBuf)> {
+        self.read_document(url, |doc| {
+            Some(
+                doc.raw_imports()
+                    .into_iter()
+                    .filter_map(|import| {
+                        doc.resolve_import(&import, config)
+                            .map(|(dir, path)| (import, dir, path))
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or_default()
+    }
+
+    /// The file at `path` and every file it imports, directly or not
+    fn reachable_files(&self, path: &Path, config: &Config) -> Vec<lspt::Url> {
+        let mut seen = vec![];
+        let mut todo: Vec<_> = file_url(path).into_iter().collect();
+        while let Some(next) = todo.pop() {
+            if seen.contains(&next) {
+                continue;
+            }
+            if let Some(paths) = self.read_and_open(&next, |doc| {
+                Some(doc.resolved_imports(config).collect::<Vec<_>>())
+            }) {
+                todo.extend(paths.iter().filter_map(|p| file_url(p)));
+            }
+            seen.push(next);
+        }
+        seen
+    }
+
+    /// The components that each import of `url` makes available, which are
+    /// the ones defined in the imported file and in everything it imports
+    fn import_components(
+        &self,
+        url: &lspt::Url,
+        config: &Config,
+    ) -> HashMap<String, HashSet<String>> {
+        self.direct_imports(url, config)
+            .into_iter()
+            .map(|(import, _, path)| {
+                let comps = self
+                    .reachable_files(&path, config)
+                    .iter()
+                    .filter_map(|file| {
+                        self.read_document(file, |doc| {
+                            Some(doc.signatures().map(|(name, _)| name).collect::<Vec<_>>())
+                        })
+                    })
+                    .flatten()
+                    .collect();
+                (import, comps)
+            })
+            .collect()
+    }
+
+    /// The import of `url` that `target` is reached through, possibly via
+    /// other imports, together with the search path that import was found in
+    fn import_reaching(
+        &self,
+        url: &lspt::Url,
+        target: &lspt::Url,
+        config: &Config,
+    ) -> Option<(String, PathBuf)> {
+        self.direct_imports(url, config)
+            .into_iter()
+            .find(|(_, _, path)| self.reachable_files(path, config).contains(target))
+            .map(|(import, dir, _)| (import, dir))
+    }
+}
+
+/// The url of the file at `path`, with symlinks resolved so that different
+/// ways of reaching the same file compare equal
+fn file_url(path: &Path) -> Option<lspt::Url> {
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    lspt::Url::from_file_path(path).ok()
+}
+
+/// `errors` placed in `doc`
+fn error_diagnostics(doc: &Document, errors: &[CalyxError]) -> Vec<lspt::Diagnostic> {
+    errors
+        .iter()
+        .filter_map(|diag| {
+            doc.byte_to_point(diag.pos_start).and_then(|s| {
+                doc.byte_to_point(diag.pos_end)
+                    .map(|e| (Range::new(s, e), diag.msg.clone()))
+            })
+        })
+        .map(|(range, message)| lspt::Diagnostic {
+            range: range.into(),
+            severity: Some(lspt::DiagnosticSeverity::ERROR),
+            code: None,
+            code_description: None,
+            source: Some("calyx".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        })
+        .inspect(|diag| log::stdout!("{diag:#?}"))
+        .collect()
 }
 
 /// TODO: turn this into a trait
@@ -207,13 +577,32 @@ impl LanguageServer for Backend {
                 )),
                 definition_provider: Some(lspt::OneOf::Left(true)),
                 completion_provider: Some(lspt::CompletionOptions {
-                    resolve_provider: Some(false),
-                    trigger_characters: Some(vec![".".to_string(), "[".to_string()]),
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(vec![
+                        ".".to_string(),
+                        "[".to_string(),
+                        "@".to_string(),
+                    ]),
                     all_commit_characters: None,
                     work_done_progress_options: Default::default(),
                     completion_item: None,
                 }),
                 hover_provider: Some(lspt::HoverProviderCapability::Simple(false)),
+                signature_help_provider: Some(lspt::SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                code_action_provider: Some(lspt::CodeActionProviderCapability::Options(
+                    lspt::CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            lspt::CodeActionKind::QUICKFIX,
+                            lspt::CodeActionKind::REFACTOR_INLINE,
+                        ]),
+                        work_done_progress_options: Default::default(),
+                        resolve_provider: Some(false),
+                    },
+                )),
                 ..Default::default()
             },
             ..Default::default()
@@ -228,12 +617,17 @@ impl LanguageServer for Backend {
 
     async fn did_open(&self, params: lspt::DidOpenTextDocumentParams) {
         self.open(params.text_document.uri.clone(), params.text_document.text);
+        self.import_envs
+            .write()
+            .unwrap()
+            .remove(&params.text_document.uri);
         self.publish_diagnostics(&params.text_document.uri).await;
     }
 
     async fn did_change_configuration(&self, params: lspt::DidChangeConfigurationParams) {
         let config: Config = serde_json::from_value(params.settings).unwrap();
         *self.config.write().unwrap() = config;
+        self.import_envs.write().unwrap().clear();
 
         // update the diagnostics on all open documents
         let open_docs: Vec<_> = self.open_docs.read().unwrap().keys().cloned().collect();
@@ -249,11 +643,32 @@ impl LanguageServer for Backend {
             }
         });
         self.update_symbols(&params.text_document.uri);
+        let url = &params.text_document.uri;
+        self.publish(url).await;
+
+        // only run the compiler once the user stops typing. The wait happens in
+        // its own task, since a sleeping handler would hold up other requests.
+        let edit = {
+            let mut edits = self.edits.write().unwrap();
+            let count = edits.entry(url.clone()).or_default();
+            *count += 1;
+            *count
+        };
+        let backend = self.clone();
+        let url = url.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DIAGNOSTICS_DELAY).await;
+            if backend.edit_count(&url) == edit {
+                backend.publish_diagnostics(&url).await;
+            }
+        });
     }
 
-    #[cfg(feature = "diagnostics")]
     async fn did_save(&self, params: lspt::DidSaveTextDocumentParams) {
         let url = &params.text_document.uri;
+        // the saved file may be imported by others, whose cached imports are
+        // out of date now
+        self.import_envs.write().unwrap().clear();
         self.publish_diagnostics(url).await;
     }
 
@@ -277,6 +692,23 @@ impl LanguageServer for Backend {
             .map(|loc| lspt::GotoDefinitionResponse::Scalar(loc)))
     }
 
+    async fn signature_help(
+        &self,
+        params: lspt::SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<lspt::SignatureHelp>> {
+        let url = &params.text_document_position_params.text_document.uri;
+        let point: Point = params.text_document_position_params.position.into();
+        let config = &self.config.read().unwrap();
+        Ok(self
+            .read_document(url, |doc| doc.signature_help(&point, config))
+            .and_then(|shr| {
+                shr.resolve(|shr, path| {
+                    let url = lspt::Url::from_file_path(path).unwrap();
+                    self.read_and_open(&url, |doc| shr.resume(config, doc))
+                })
+            }))
+    }
+
     async fn completion(
         &self,
         params: lspt::CompletionParams,
@@ -284,10 +716,11 @@ impl LanguageServer for Backend {
         let url = &params.text_document_position.text_document.uri;
         let point: Point = params.text_document_position.position.into();
         let trigger_char = params.context.and_then(|cc| cc.trigger_character);
-        let config = self.config.read().unwrap();
-        Ok(self
-            .read_document(url, |doc| {
-                doc.complete(trigger_char.as_deref(), &point, &config)
+        let reses = {
+            let config = self.config.read().unwrap();
+            let sigs = self.signature_env(url, &config);
+            self.read_document(url, |doc| {
+                doc.complete(trigger_char.as_deref(), &point, &config, &sigs)
             })
             .map(|reses| {
                 reses
@@ -295,17 +728,53 @@ impl LanguageServer for Backend {
                     .filter_map(|res| {
                         res.resolve(|res, path| {
                             let url = lspt::Url::from_file_path(path).unwrap();
-                            self.read_and_open(&url, |doc| res.resume(&config, doc))
+                            // there can be many library files, so they are
+                            // only parsed and not kept open
+                            match res {
+                                CompletionRes::ContinueLibrary(..) => {
+                                    self.read_file(&url, |doc| res.resume(&config, doc))
+                                }
+                                _ => self.read_and_open(&url, |doc| res.resume(&config, doc)),
+                            }
                         })
                     })
                     .flatten()
                     .collect::<Vec<_>>()
             })
-            .map(|completions| {
-                lspt::CompletionResponse::Array(
-                    completions.into_iter().map(|ci| ci.into()).collect(),
-                )
-            }))
+        };
+        let Some(completions) = reses else {
+            return Ok(None);
+        };
+        Ok(Some(lspt::CompletionResponse::Array(
+            completions.into_iter().map(|ci| ci.into()).collect(),
+        )))
+    }
+
+    async fn completion_resolve(
+        &self,
+        mut item: lspt::CompletionItem,
+    ) -> jsonrpc::Result<lspt::CompletionItem> {
+        let data = item
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<ResolveData>(data).ok());
+        if let Some(data) = data {
+            item.documentation = self.read_file(&data.url, |doc| doc.definition_docs(&data.name));
+        }
+        Ok(item)
+    }
+
+    async fn code_action(
+        &self,
+        params: lspt::CodeActionParams,
+    ) -> jsonrpc::Result<Option<lspt::CodeActionResponse>> {
+        let url = &params.text_document.uri;
+        let point: Point = params.range.start.into();
+        Ok(self
+            .read_document(url, |doc| {
+                Some(doc.code_actions(&point, &params.context.diagnostics))
+            })
+            .filter(|actions| !actions.is_empty()))
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use tower_lsp::lsp_types as lspt;
+
+use crate::{
+    convert::Point,
+    document::{Context, Document},
+    query_result::QueryResult2,
+    ts_utils::ParentUntil,
+    Config,
+};
+
+#[derive(Clone, Debug)]
+pub enum SigHelpRes {
+    Found(lspt::SignatureHelp),
+    /// Keep looking for the component `name` in `paths`, with the argument
+    /// at the given index being edited. The last field has the files that
+    /// were already searched on the way, so that cyclic imports terminate.
+    Continue(Vec<PathBuf>, String, u32, Vec<PathBuf>),
+}
+
+impl QueryResult2 for SigHelpRes {
+    type Data = lspt::SignatureHelp;
+    type Needle = String;
+
+    fn found(&self) -> Option<Self::Data> {
+        match self {
+            SigHelpRes::Found(help) => Some(help.clone()),
+            SigHelpRes::Continue(..) => None,
+        }
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            SigHelpRes::Found(_) => vec![],
+            SigHelpRes::Continue(paths, ..) => paths.clone(),
+        }
+    }
+
+    fn resume(&self, config: &Config, doc: &Document) -> Option<Self> {
+        match self {
+            SigHelpRes::Found(_) => Some(self.clone()),
+            SigHelpRes::Continue(_, name, active, chain) => doc
+                .signature_help_for(name, *active)
+                .map(SigHelpRes::Found)
+                .or_else(|| Some(doc.continue_search(config, name.to_string(), *active, chain))),
+        }
+    }
+}
+
+pub trait SignatureHelpProvider {
+    fn signature_help(&self, point: &Point, config: &Config) -> Option<SigHelpRes>;
+}
+
+impl SignatureHelpProvider for Document {
+    fn signature_help(&self, point: &Point, config: &Config) -> Option<SigHelpRes> {
+        if !matches!(self.context_at_point(point), Context::Cells) {
+            return None;
+        }
+        static RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"=\s*(\w+)\s*\(([^()]*)$").unwrap());
+        let (before, _) = self.statement_at(point)?;
+        let caps = RE.captures(before)?;
+        let name = caps[1].to_string();
+        let active = caps[2].matches(',').count() as u32;
+        Some(
+            self.signature_help_for(&name, active)
+                .map(SigHelpRes::Found)
+                .unwrap_or_else(|| self.continue_search(config, name, active, &[])),
+        )
+    }
+}
+
+impl Document {
+    /// Search the imports of this file next, skipping the ones in `chain`
+    fn continue_search(
+        &self,
+        config: &Config,
+        name: String,
+        active: u32,
+        chain: &[PathBuf],
+    ) -> SigHelpRes {
+        let mut chain = chain.to_vec();
+        chain.extend(self.url.to_file_path().ok());
+        let paths = self
+            .resolved_imports(config)
+            .filter(|path| !chain.contains(path))
+            .collect();
+        SigHelpRes::Continue(paths, name, active, chain)
+    }
+
+    /// Signature help for instantiating the component `name`, if it is defined
+    /// in this file.
+    fn signature_help_for(&self, name: &str, active: u32) -> Option<lspt::SignatureHelp> {
+        let (_, sig) = self.signatures().find(|(n, _)| n == name)?;
+        let def = self
+            .components()
+            .find(|n| self.node_text(n) == name)
+            .and_then(|n| n.parent_until_names(&["component", "primitive"]));
+
+        // remember where each parameter starts so the client can highlight it
+        let mut label = format!("{name}(");
+        let mut parameters = vec![];
+        for (i, param) in sig.params.iter().enumerate() {
+            if i > 0 {
+                label.push_str(", ");
+            }
+            let start = label.len() as u32;
+            label.push_str(param);
+            parameters.push(lspt::ParameterInformation {
+                label: lspt::ParameterLabel::LabelOffsets([start, label.len() as u32]),
+                documentation: None,
+            });
+        }
+        label.push(')');
+
+        Some(lspt::SignatureHelp {
+            signatures: vec![lspt::SignatureInformation {
+                label,
+                documentation: def.and_then(|d| self.doc_comment(&d)).map(|doc| {
+                    lspt::Documentation::MarkupContent(lspt::MarkupContent {
+                        kind: lspt::MarkupKind::Markdown,
+                        value: doc,
+                    })
+                }),
+                parameters: Some(parameters),
+                active_parameter: Some(active),
+            }],
+            active_signature: Some(0),
+            active_parameter: Some(active),
+        })
+    }
+}
@@ -31,3 +31,40 @@ impl ParentUntil for Node<'_> {
         self.parent_until(|p| names.iter().find(|n| p.kind() == n.as_ref()).is_some())
     }
 }
+
+pub trait Descendants: Sized {
+    /// All nodes below this one (named and anonymous) in document order.
+    fn descendants(&self) -> Vec<Self>;
+
+    /// The first anonymous token below this node with the given text.
+    fn find_token(&self, token: &str) -> Option<Self> {
+        self.descendants().into_iter().find(|n| n.is_token(token))
+    }
+
+    fn is_token(&self, token: &str) -> bool;
+
+    /// Whether one of the direct children of this node is the given token.
+    fn has_child_token(&self, token: &str) -> bool;
+}
+
+impl Descendants for Node<'_> {
+    fn descendants(&self) -> Vec<Self> {
+        let mut res = vec![];
+        let mut cursor = self.walk();
+        for child in self.children(&mut cursor) {
+            res.push(child);
+            res.extend(child.descendants());
+        }
+        res
+    }
+
+    fn is_token(&self, token: &str) -> bool {
+        !self.is_named() && self.kind() == token
+    }
+
+    fn has_child_token(&self, token: &str) -> bool {
+        let mut cursor = self.walk();
+        let res = self.children(&mut cursor).any(|c| c.is_token(token));
+        res
+    }
+}